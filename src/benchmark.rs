@@ -2,12 +2,13 @@ use std:: {
     fs,
     io,
     path::Path,
-    sync::{Arc, Mutex},
 };
 
 use colored::Colorize;
 
-use crate::{RankMethod, index_directory, model::{InMemoryModel, Model}};
+use docsense::{Backend, DocSenseError, Index, RankMethod};
+
+use crate::index_directory;
 
 // Benchmark function logic
 pub fn calculate_dir_size(dir_path: &Path) -> io::Result<u64> {
@@ -26,7 +27,7 @@ pub fn calculate_dir_size(dir_path: &Path) -> io::Result<u64> {
     Ok(total_size)
 }
 
-pub fn run_benchmark(dir_path: &Path) -> Result<(), ()> {
+pub fn run_benchmark(dir_path: &Path, backend: Backend, jobs: usize) -> Result<(), DocSenseError> {
     println!("\n{}: Starting benchmarks on '{}'", "INFO".cyan(), dir_path.display());
     
     // 1. Storage Efficiency
@@ -34,15 +35,16 @@ pub fn run_benchmark(dir_path: &Path) -> Result<(), ()> {
     println!("Raw Corpus Size: {:.2} MB", raw_size as f64 / 1_048_576.0);
     
     // 2. Indexing Throughput
-    let model = Arc::new(Mutex::<InMemoryModel>::new(Default::default()));
-    println!("{}: Indexing directory (this may take a while)...", "INFO".cyan());
-    let start_time = std::time::Instant::now();
-    
     let mut index_path = dir_path.to_path_buf();
-    index_path.push(".docsense.benchmark.json");
-    let index_str = index_path.to_str().unwrap().to_string();
+    index_path.push(backend.default_index_name().replacen(".docsense", ".docsense.benchmark", 1));
+
+    // Always index from scratch so the timing covers the whole corpus
+    let _ = fs::remove_file(&index_path);
+    let index = Index::create(&index_path, backend)?;
+    println!("{}: Indexing directory with {jobs} workers (this may take a while)...", "INFO".cyan());
+    let start_time = std::time::Instant::now();
     
-    index_directory(dir_path, Arc::clone(&model), Some(&index_str))?;
+    index_directory(&index, dir_path, jobs)?;
     
     let indexing_duration = start_time.elapsed();
     let index_size = fs::metadata(&index_path).map(|m| m.len()).unwrap_or(0);
@@ -60,6 +62,7 @@ pub fn run_benchmark(dir_path: &Path) -> Result<(), ()> {
         "missingkeywordthatdoesnotexist",
     ];
 
+    let model = index.model();
     let model_lock = model.lock().unwrap();
 
     for rank_method in vec![RankMethod::Tfidf, RankMethod::Bm25] {
@@ -71,7 +74,7 @@ pub fn run_benchmark(dir_path: &Path) -> Result<(), ()> {
             let query = query_str.chars().collect::<Vec<char>>();
             
             // Warm up
-            let _ = model_lock.search_query(&query, &model_lock, rank_method.clone());
+            let _ = model_lock.search_query(&query, rank_method.clone());
             
             let iters = 10;
             let mut total_duration = std::time::Duration::new(0, 0);
@@ -79,7 +82,7 @@ pub fn run_benchmark(dir_path: &Path) -> Result<(), ()> {
             
             for _ in 0..iters {
                 let start_query = std::time::Instant::now();
-                let results = model_lock.search_query(&query, &model_lock, rank_method.clone()).unwrap_or_default();
+                let results = model_lock.search_query(&query, rank_method.clone()).unwrap_or_default();
                 total_duration += start_query.elapsed();
                 
                 if let Some((_, score)) = results.first() {
@@ -0,0 +1,237 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use super::error::DocSenseError;
+use super::model::*;
+use super::parsers::Fields;
+
+/* Compact on-disk format of the in-memory model, much smaller and faster to load than JSON.
+
+   Layout, header integers are little endian:
+       magic        8 bytes   MAGIC
+       version      u32       INDEX_VERSION the file was written with
+       checksum     u32       CRC32 of the payload
+       length       u64       Length of the payload in bytes
+       payload
+
+   The payload starts with the next doc id, the analysis settings and the format settings (count,
+   then extension and settings of each). Every doc is stored with the
+   path and locator (empty for none) of the file part it was parsed from, the kind of its text,
+   the archive member it was parsed from (empty for none), its text and its fields (count, then
+   name and value of each).
+   Inside the payload every integer is a LEB128 varint and every string its byte length followed
+   by its UTF-8 bytes. Terms are stored once in a sorted dictionary and referred to by their index.
+   Postings store doc ids and positions as deltas to the previous one, so most take a single byte.
+   The global term frequencies, the per document frequency tables, the documents of every file
+   and the token total are all derived from the postings and docs when loading, so they are not stored at all. */
+pub const MAGIC: [u8; 8] = *b"\x89DSIDX\r\n";
+const HEADER_LENGTH: usize = 24;
+
+/* Whether the first bytes of a file are those of a binary index */
+pub fn is_binary_index(header: &[u8]) -> bool {
+    header.starts_with(&MAGIC)
+}
+
+fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    put_varint(out, s.len() as u64);
+    out.extend_from_slice(s.as_bytes());
+}
+
+/* Reads the payload back, yielding None once it runs out of bytes or hits malformed data */
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn varint(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = self.bytes.split_first()?;
+            self.bytes = rest;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn usize(&mut self) -> Option<usize> {
+        self.varint().and_then(|value| usize::try_from(value).ok())
+    }
+
+    fn str(&mut self) -> Option<&'a str> {
+        let length = self.usize()?;
+        if length > self.bytes.len() {
+            return None;
+        }
+        let (s, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        std::str::from_utf8(s).ok()
+    }
+}
+
+pub fn encode(model: &InMemoryModel) -> Vec<u8> {
+    let mut payload = Vec::new();
+    put_varint(&mut payload, model.next_id as u64);
+    put_varint(&mut payload, model.analysis.stem_code as u64);
+    put_varint(&mut payload, model.formats.len() as u64);
+    for (ext, settings) in &model.formats {
+        put_str(&mut payload, ext);
+        put_str(&mut payload, settings);
+    }
+
+    let mut terms = model.index.keys().map(String::as_str).collect::<Vec<_>>();
+    terms.sort_unstable();
+    put_varint(&mut payload, terms.len() as u64);
+    for term in &terms {
+        put_str(&mut payload, term);
+    }
+
+    put_varint(&mut payload, model.docs.len() as u64);
+    for (id, doc) in &model.docs {
+        let modified = doc.last_modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+        put_varint(&mut payload, *id as u64);
+        put_str(&mut payload, &doc.path.to_string_lossy());
+        put_str(&mut payload, doc.locator.as_deref().unwrap_or_default());
+        put_varint(&mut payload, encode_kind(doc.kind) as u64);
+        put_str(&mut payload, doc.member.as_deref().unwrap_or_default());
+        put_varint(&mut payload, doc.count as u64);
+        put_varint(&mut payload, modified.as_secs());
+        put_varint(&mut payload, modified.subsec_nanos() as u64);
+        put_str(&mut payload, &doc.text);
+        put_varint(&mut payload, doc.fields.len() as u64);
+        for (name, value) in &doc.fields {
+            put_str(&mut payload, name);
+            put_str(&mut payload, value);
+        }
+    }
+
+    // Postings in the order of the dictionary, so the term needs no reference
+    for term in &terms {
+        let postings = &model.index[*term];
+        put_varint(&mut payload, postings.len() as u64);
+        let mut previous_doc = 0;
+        for posting in postings {
+            put_varint(&mut payload, (posting.doc - previous_doc) as u64);
+            put_varint(&mut payload, posting.positions.len() as u64);
+            let mut previous_position = 0;
+            for &position in &posting.positions {
+                put_varint(&mut payload, (position - previous_position) as u64);
+                previous_position = position;
+            }
+            previous_doc = posting.doc;
+        }
+    }
+
+    let mut bytes = Vec::with_capacity(HEADER_LENGTH + payload.len());
+    bytes.extend_from_slice(&MAGIC);
+    bytes.extend_from_slice(&INDEX_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+    bytes.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    bytes.extend_from_slice(&payload);
+    bytes
+}
+
+/* Load a model from the bytes of a binary index file, `index_path` is only used for reporting */
+pub fn decode(bytes: &[u8], index_path: &Path) -> Result<InMemoryModel, DocSenseError> {
+    let corrupt = |reason: &str| DocSenseError::IndexCorrupt { path: index_path.to_path_buf(), reason: reason.to_owned() };
+
+    if bytes.len() < HEADER_LENGTH || !is_binary_index(bytes) {
+        return Err(corrupt("the header is missing"));
+    }
+    let version = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+    let checksum = u32::from_le_bytes(bytes[12..16].try_into().unwrap());
+    let length = u64::from_le_bytes(bytes[16..24].try_into().unwrap());
+
+    // The payload of another version may be laid out differently, leave it to the caller to rebuild it
+    if version != INDEX_VERSION {
+        return Ok(InMemoryModel { version, ..Default::default() });
+    }
+
+    let payload = &bytes[HEADER_LENGTH..];
+    if payload.len() as u64 != length {
+        return Err(corrupt("it is truncated"));
+    }
+    if crc32fast::hash(payload) != checksum {
+        return Err(corrupt("its checksum does not match"));
+    }
+
+    decode_payload(payload, version).ok_or_else(|| corrupt("its contents could not be read"))
+}
+
+fn decode_payload(payload: &[u8], version: u32) -> Option<InMemoryModel> {
+    let mut reader = Reader { bytes: payload };
+    let mut model = InMemoryModel { version, next_id: reader.varint()? as DocId, ..Default::default() };
+    model.analysis.stem_code = reader.varint()? != 0;
+    for _ in 0..reader.usize()? {
+        model.formats.insert(reader.str()?.to_owned(), reader.str()?.to_owned());
+    }
+
+    let term_count = reader.usize()?;
+    let mut terms = Vec::with_capacity(term_count.min(payload.len()));
+    for _ in 0..term_count {
+        terms.push(reader.str()?.to_owned());
+    }
+
+    let doc_count = reader.usize()?;
+    let mut docs = HashMap::with_capacity(doc_count.min(payload.len()));
+    for _ in 0..doc_count {
+        let id = reader.varint()? as DocId;
+        let path = PathBuf::from(reader.str()?);
+        let locator = Some(reader.str()?).filter(|locator| !locator.is_empty()).map(str::to_owned);
+        let kind = decode_kind(u8::try_from(reader.varint()?).ok()?)?;
+        let member = Some(reader.str()?).filter(|member| !member.is_empty()).map(str::to_owned);
+        let count = reader.usize()?;
+        let last_modified = UNIX_EPOCH.checked_add(Duration::from_secs(reader.varint()?))?.checked_add(Duration::from_nanos(reader.varint()?))?;
+        let text = reader.str()?.to_owned();
+        let mut fields = Fields::new();
+        for _ in 0..reader.usize()? {
+            fields.insert(reader.str()?.to_owned(), reader.str()?.to_owned());
+        }
+        docs.insert(id, Doc { path, locator, kind, member, count, ft: FreqTable::new(), text, fields, last_modified });
+    }
+
+    for term in terms {
+        let posting_count = reader.usize()?;
+        let mut postings = Vec::with_capacity(posting_count.min(payload.len()));
+        let mut doc: DocId = 0;
+        for _ in 0..posting_count {
+            doc = doc.checked_add(DocId::try_from(reader.varint()?).ok()?)?;
+            let tf = reader.usize()?;
+            let mut positions = Vec::with_capacity(tf.min(payload.len()));
+            let mut position: u32 = 0;
+            for _ in 0..tf {
+                position = position.checked_add(u32::try_from(reader.varint()?).ok()?)?;
+                positions.push(position);
+            }
+
+            let stored = docs.get_mut(&doc)?;
+            stored.ft.insert(term.clone(), tf);
+            postings.push(Posting { doc, tf, positions });
+        }
+        model.gtf.insert(term.clone(), postings.len());
+        model.index.insert(term, postings);
+    }
+
+    for (&id, doc) in &docs {
+        model.total_tokens += doc.count;
+        model.files.entry(doc.path.clone()).or_default().push(id);
+    }
+    for ids in model.files.values_mut() {
+        ids.sort_unstable();
+    }
+    model.docs = docs;
+    Some(model)
+}
@@ -0,0 +1,80 @@
+use std::{fs, io, path::Path};
+
+use serde::Deserialize;
+
+use super::error::DocSenseError;
+use super::parsers::{field_name, is_built_in, Converter, DataSettings, ParseOptions};
+
+/* Name of the config file looked for in an indexed directory */
+pub const CONFIG_FILE_NAME: &str = ".docsense.toml";
+
+/* Settings of how the files of a directory are read, kept in a TOML file next to them so a team
+   can share them, e.g.
+
+       [[converter]]
+       extensions = ["rst"]
+       command = "pandoc -t plain {}"
+       timeout = 60
+
+       [data]
+       records = true */
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /* External commands for formats DocSense has no parser of, see `Converter` */
+    #[serde(default, rename = "converter")]
+    pub converters: Vec<Converter>,
+    /* How tables and JSON are indexed, see `DataSettings` */
+    #[serde(default)]
+    pub data: DataSettings,
+    /* Converters of the file which were left out, see `load_for_directory` */
+    #[serde(skip)]
+    pub ignored_converters: usize,
+}
+
+impl Config {
+    /* Read the config at `config_path`, or the default one when there is no such file */
+    pub fn load(config_path: &Path) -> Result<Self, DocSenseError> {
+        let contents = match fs::read_to_string(config_path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(DocSenseError::io(config_path, err)),
+        };
+        let invalid = |message: String| DocSenseError::Config { path: config_path.to_path_buf(), message };
+
+        let config: Config = toml::from_str(&contents).map_err(|err| invalid(err.message().to_owned()))?;
+        for (i, converter) in config.converters.iter().enumerate() {
+            let number = i + 1;
+            if converter.args().is_none() {
+                return Err(invalid(format!("command `{}` of converter {number} is empty or not properly quoted", converter.command)));
+            }
+            if converter.extensions.is_empty() && converter.mime_types.is_empty() {
+                return Err(invalid(format!("converter {number} has neither extensions nor mime_types")));
+            }
+            if converter.timeout == 0 {
+                return Err(invalid(format!("timeout of converter {number} must be at least one second")));
+            }
+            if !is_built_in(&converter.output) {
+                return Err(invalid(format!("output of converter {number} is {}, which DocSense has no parser of", converter.output)));
+            }
+        }
+        if let Some(name) = config.data.fields.iter().find(|name| field_name(name).is_none()) {
+            return Err(invalid(format!("field \"{name}\" of [data] has no letter, which field names start with")));
+        }
+        Ok(config)
+    }
+
+    /* Read the config file of the directory at `dir_path`, if it has one. Its converters are left
+       out: the directory may come from anyone (a checkout, a download), and indexing it must not
+       run the commands it names. Converters only come from a config loaded on purpose with `load`. */
+    pub fn load_for_directory(dir_path: &Path) -> Result<Self, DocSenseError> {
+        let mut config = Self::load(&dir_path.join(CONFIG_FILE_NAME))?;
+        config.ignored_converters = std::mem::take(&mut config.converters).len();
+        Ok(config)
+    }
+
+    /* Options to parse files with as the config sets up, e.g. for `Index::with_parse_options` */
+    pub fn parse_options(self) -> ParseOptions {
+        ParseOptions { converters: self.converters, data: self.data }
+    }
+}
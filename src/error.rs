@@ -0,0 +1,85 @@
+use std::{error::Error, fmt, io, path::{Path, PathBuf}};
+
+use super::index::Backend;
+
+/* Everything that can go wrong while indexing or searching. Errors are passed up to the caller
+   instead of being printed where they happen, so only the CLI decides how to report them. */
+#[derive(Debug)]
+pub enum DocSenseError {
+    /* Reading or writing a file or directory failed */
+    Io { path: PathBuf, source: io::Error },
+    /* A document could not be parsed as the format its extension says it is */
+    Parse { path: PathBuf, format: String, message: String },
+    /* There is no parser for the extension of a document */
+    UnsupportedFormat { path: PathBuf },
+    /* There is no index at the given path */
+    IndexNotFound { path: PathBuf },
+    /* The index file is damaged or not an index at all */
+    IndexCorrupt { path: PathBuf, reason: String },
+    /* The index was built by an older version and has to be rebuilt before it can be searched */
+    IndexOutdated { path: PathBuf },
+    /* The index is stored in another format than the one asked for */
+    IndexBackend { path: PathBuf, stored: Backend, requested: Backend },
+    /* The query holds nothing that can be searched for, e.g. only stop words or punctuation */
+    Query { query: String, reason: String },
+    /* The SQLite backend failed */
+    Database(rusqlite::Error),
+    /* Watching a directory for changes failed */
+    Watch(notify::Error),
+    /* The web server could not listen at the given address */
+    Bind { address: String, message: String },
+    /* The config file of a directory is not valid */
+    Config { path: PathBuf, message: String },
+}
+
+impl DocSenseError {
+    pub fn io(path: &Path, source: io::Error) -> Self {
+        DocSenseError::Io { path: path.to_path_buf(), source }
+    }
+
+    pub fn parse(path: &Path, format: &str, message: impl ToString) -> Self {
+        DocSenseError::Parse { path: path.to_path_buf(), format: format.to_owned(), message: message.to_string() }
+    }
+}
+
+impl fmt::Display for DocSenseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DocSenseError::Io { path, source } => write!(f, "Could not access {} as {source}", path.display()),
+            DocSenseError::Parse { path, format, message } => write!(f, "Failed to parse {} as {format}: {message}", path.display()),
+            DocSenseError::UnsupportedFormat { path } => write!(f, "Format of {} is unsupported", path.display()),
+            DocSenseError::IndexNotFound { path } => write!(f, "Index file {} does not exist", path.display()),
+            DocSenseError::IndexCorrupt { path, reason } => write!(f, "Index file {} is corrupt, {reason}", path.display()),
+            DocSenseError::IndexOutdated { path } => write!(f, "Index {} was built by an older version of DocSense, rebuild it with the index command", path.display()),
+            DocSenseError::IndexBackend { path, stored, requested } => write!(f, "Index {path} is stored as {stored}, not {requested}, convert it with `convert {path} <new index> --to {requested}`", path = path.display()),
+            DocSenseError::Query { query, reason } => write!(f, "Query '{query}' {reason}"),
+            DocSenseError::Database(err) => write!(f, "SQLite index query failed as {err}"),
+            DocSenseError::Watch(err) => write!(f, "File system watcher failed as {err}"),
+            DocSenseError::Bind { address, message } => write!(f, "Could not start server at {address} as {message}"),
+            DocSenseError::Config { path, message } => write!(f, "Config file {} is invalid, {message}", path.display()),
+        }
+    }
+}
+
+impl Error for DocSenseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DocSenseError::Io { source, .. } => Some(source),
+            DocSenseError::Database(err) => Some(err),
+            DocSenseError::Watch(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for DocSenseError {
+    fn from(err: rusqlite::Error) -> Self {
+        DocSenseError::Database(err)
+    }
+}
+
+impl From<notify::Error> for DocSenseError {
+    fn from(err: notify::Error) -> Self {
+        DocSenseError::Watch(err)
+    }
+}
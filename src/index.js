@@ -11,15 +11,18 @@ async function loadStats() {
 
 // Derive a file extension from an absolute path string
 function extOf(path) {
-  const dot = path.lastIndexOf(".");
-  return dot !== -1 ? path.slice(dot + 1).toLowerCase() : "";
+  // Only the dot of the file name counts, folders (and folders of archives) may have one too
+  const { name } = splitPath(path);
+  const dot = name.lastIndexOf(".");
+  return dot !== -1 ? name.slice(dot + 1).toLowerCase() : "";
 }
 
 // Map extension → badge CSS class
 function extClass(ext) {
-  if (["xml", "xhtml"].includes(ext)) return "ext-xml";
+  if (["xml", "xhtml", "html", "htm"].includes(ext)) return "ext-xml";
   if (ext === "pdf") return "ext-pdf";
-  if (["txt", "md"].includes(ext)) return "ext-txt";
+  if (["txt", "md", "markdown", "epub", "docx", "pptx", "xlsx", "odt", "odp", "ods", "eml", "mbox", "tex", "bib", "csv", "tsv", "json", "jsonl", "ndjson",
+       "rs", "py", "c", "h", "js", "ts", "go", "java"].includes(ext)) return "ext-txt";
   return "ext-default";
 }
 
@@ -31,6 +34,20 @@ function splitPath(fullPath) {
   return { name: fullPath.slice(idx + 1), dir: fullPath.slice(0, idx) };
 }
 
+// Snippet text with its [start, end) highlights wrapped in <mark>. Offsets count code points,
+// not UTF-16 units, so the text is split with Array.from before slicing.
+function snippetHtml({ text, highlights }) {
+  const chars = Array.from(text);
+  let html = "";
+  let at = 0;
+  for (const [start, end] of highlights) {
+    html += escHtml(chars.slice(at, start).join(""));
+    html += `<mark>${escHtml(chars.slice(start, end).join(""))}</mark>`;
+    at = end;
+  }
+  return html + escHtml(chars.slice(at).join(""));
+}
+
 // Render the results list into #results
 function renderResults(data) {
   const container = document.getElementById("results");
@@ -57,22 +74,27 @@ function renderResults(data) {
   const list = document.createElement("div");
   list.className = "result-list";
 
-  for (const [path, rank] of data) {
+  for (const { path, locator, rank, fields, snippets } of data) {
     const ext = extOf(path);
     const { name, dir } = splitPath(path);
+    // Documents with a title are listed by it (and the chapter or page matched), along with their whole path
+    const page = locator && locator.startsWith("page=") ? "page " + locator.slice(5) : "";
+    const title = fields && [fields.title || fields.subject, fields.chapter || page].filter(Boolean).join(" — ");
+    const subtitle = title ? (locator ? path + "#" + locator : path) : dir;
 
     const a = document.createElement("a");
     a.className = "result-item";
-    a.href = "/file?path=" + encodeURIComponent(path);
+    a.href = "/file?path=" + encodeURIComponent(path) + (locator ? "#" + encodeURI(locator) : "");
     a.target = "_blank";
     a.rel = "noopener noreferrer";
     a.setAttribute("aria-label", name);
 
     a.innerHTML = `
-      <div class="ext-badge ${extClass(ext)}">${ext || "?"}</div>
+      <div class="ext-badge ${extClass(ext)}">${escHtml(ext || "?")}</div>
       <div class="result-body">
-        <div class="result-filename">${escHtml(name)}</div>
-        ${dir ? `<div class="result-path">${escHtml(dir)}</div>` : ""}
+        <div class="result-filename">${escHtml(title || name)}</div>
+        ${subtitle ? `<div class="result-path">${escHtml(subtitle)}</div>` : ""}
+        ${snippets.map((snippet) => `<div class="result-snippet">${snippetHtml(snippet)}</div>`).join("")}
       </div>
       <div class="result-rank">${rank.toFixed(3)}</div>`;
 
@@ -123,6 +145,11 @@ async function search(prompt) {
       body: prompt,
     });
 
+    if (res.status === 400) {
+      // The server explains what is wrong with the query
+      renderError((await res.text()).replace(/^400: /, ""));
+      return;
+    }
     if (!res.ok) {
       renderError(`Server error ${res.status}`);
       return;
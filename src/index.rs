@@ -0,0 +1,357 @@
+use std::{
+    collections::HashSet,
+    fmt,
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use clap::ValueEnum;
+
+use super::binary;
+use super::error::DocSenseError;
+use super::indexer::{self, IndexEvent, IndexSummary};
+use super::model::*;
+use super::parsers::{inner_extension, parse_file_by_ext, ParseOptions, ParsedDocument};
+
+/* How an index is stored on disk */
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Backend {
+    Json,
+    Binary,
+    Sqlite
+}
+
+impl Backend {
+    /* Name of the index file placed inside an indexed directory */
+    pub fn default_index_name(&self) -> &'static str {
+        match self {
+            Backend::Json => ".docsense.json",
+            Backend::Binary => ".docsense.bin",
+            Backend::Sqlite => ".docsense.sqlite3",
+        }
+    }
+
+    /* Storage format of an existing index file, told apart by its first bytes */
+    pub fn detect(index_path: &Path) -> Result<Backend, DocSenseError> {
+        let mut header = Vec::with_capacity(16);
+        File::open(index_path)
+            .and_then(|file| file.take(16).read_to_end(&mut header))
+            .map_err(|err| DocSenseError::io(index_path, err))?;
+
+        if binary::is_binary_index(&header) {
+            Ok(Backend::Binary)
+        } else if header.starts_with(b"SQLite format 3\0") {
+            Ok(Backend::Sqlite)
+        } else if header.trim_ascii_start().starts_with(b"{") {
+            Ok(Backend::Json)
+        } else {
+            Err(DocSenseError::IndexCorrupt { path: index_path.to_path_buf(), reason: "it is stored in none of the formats of DocSense".to_owned() })
+        }
+    }
+}
+
+/* Named the way --backend and --to take it, e.g. `sqlite` */
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Backend::Json => "json",
+            Backend::Binary => "binary",
+            Backend::Sqlite => "sqlite",
+        };
+        f.write_str(name)
+    }
+}
+
+/* How `Index::search` ranks and cuts off its results */
+#[derive(Clone, Debug)]
+pub struct SearchOptions {
+    pub rank_method: RankMethod,
+    pub limit: usize,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self { rank_method: RankMethod::Bm25, limit: 20 }
+    }
+}
+
+/* A searchable index of documents, the entry point for embedding DocSense.
+   Clones are cheap and share the same model, so an index can be searched from
+   several threads while another one keeps it up to date. Files are parsed with
+   the options it was given with `with_parse_options`, each index its own.
+
+       let index = Index::create(".docsense.bin", Backend::Binary)?;
+       index.add_text("notes/todo.txt", "Buy milk and eggs")?;
+       for result in index.search("milk", &SearchOptions::default())? {
+           println!("{} - {}", result.path.display(), result.rank);
+       }
+       index.save()?; */
+#[derive(Clone)]
+pub struct Index {
+    path: Option<PathBuf>,
+    model: Arc<Mutex<dyn Model + Send>>,
+    options: Arc<ParseOptions>,
+}
+
+impl Index {
+    /* Open an existing index, whatever format it is stored in */
+    pub fn open(index_path: impl AsRef<Path>) -> Result<Self, DocSenseError> {
+        let index_path = index_path.as_ref();
+        if !index_path.exists() {
+            return Err(DocSenseError::IndexNotFound { path: index_path.to_path_buf() });
+        }
+        let backend = Backend::detect(index_path)?;
+        let (model, outdated) = load_model(index_path, backend, true)?;
+        if outdated {
+            return Err(DocSenseError::IndexOutdated { path: index_path.to_path_buf() });
+        }
+        Ok(Self { path: Some(index_path.to_path_buf()), model, options: Arc::default() })
+    }
+
+    /* Open the index at `index_path`, or start a new one stored as `backend` when there is none.
+       An existing index has to be stored as `backend` already, `convert` changes its format. One
+       built by an older version starts out empty. */
+    pub fn create(index_path: impl AsRef<Path>, backend: Backend) -> Result<Self, DocSenseError> {
+        let index_path = index_path.as_ref();
+        if index_path.exists() {
+            let stored = Backend::detect(index_path)?;
+            if stored != backend {
+                return Err(DocSenseError::IndexBackend { path: index_path.to_path_buf(), stored, requested: backend });
+            }
+        }
+        let (model, _) = load_model(index_path, backend, false)?;
+        Ok(Self { path: Some(index_path.to_path_buf()), model, options: Arc::default() })
+    }
+
+    /* An index that only lives in memory, `save` does nothing for it */
+    pub fn in_memory() -> Self {
+        Self { path: None, model: Arc::new(Mutex::new(InMemoryModel::default())), options: Arc::default() }
+    }
+
+    /* Parse the files added from now on with `options`, e.g. to run converters for other formats */
+    pub fn with_parse_options(mut self, options: ParseOptions) -> Self {
+        self.options = Arc::new(options);
+        self
+    }
+
+    /* How files are parsed before they are added */
+    pub fn parse_options(&self) -> &ParseOptions {
+        &self.options
+    }
+
+    /* Where the index is stored, None for an in-memory one */
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /* The model behind the index, for driving the indexer directly */
+    pub fn model(&self) -> Arc<Mutex<dyn Model + Send>> {
+        Arc::clone(&self.model)
+    }
+
+    /* Add a document or replace everything indexed under `path` before. `path` only names the
+       document, nothing is read from it. */
+    pub fn add_text(&self, path: impl Into<PathBuf>, text: &str) -> Result<(), DocSenseError> {
+        self.add_document(path, ParsedDocument::from(text.to_owned()))
+    }
+
+    /* Same as `add_text`, for a document which also has fields */
+    pub fn add_document(&self, path: impl Into<PathBuf>, document: ParsedDocument) -> Result<(), DocSenseError> {
+        self.add_documents(path, vec![document])
+    }
+
+    /* Same as `add_text`, for a file made up of several documents told apart by their locator */
+    pub fn add_documents(&self, path: impl Into<PathBuf>, documents: Vec<ParsedDocument>) -> Result<(), DocSenseError> {
+        let mut model = self.model.lock().unwrap();
+        let analysis = model.analysis()?;
+        let documents = documents.into_iter().map(|document| AnalyzedDocument::new(document, &analysis)).collect();
+        model.add_documents(path.into(), documents, SystemTime::now())
+    }
+
+    /* Parse a file by its extension and add it, replacing an earlier version of it. Returns the
+       members of an archive which could not be parsed, the rest of the archive is added anyway.
+       When the file can not be parsed at all, the earlier version is removed too. */
+    pub fn add_file(&self, path: impl AsRef<Path>) -> Result<Vec<DocSenseError>, DocSenseError> {
+        let path = fs::canonicalize(path.as_ref()).map_err(|err| DocSenseError::io(path.as_ref(), err))?;
+        let last_modified = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .map_err(|err| DocSenseError::io(&path, err))?;
+        let parsed = match parse_file_by_ext(&path, &self.options) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                self.remove(&path)?;
+                return Err(err);
+            }
+        };
+        let analysis = self.analysis()?;
+        let documents = parsed.documents.into_iter().map(|document| AnalyzedDocument::new(document, &analysis)).collect();
+        self.model.lock().unwrap().add_documents(path, documents, last_modified)?;
+        Ok(parsed.skipped)
+    }
+
+    /* Remove every document indexed under `path` */
+    pub fn remove(&self, path: impl AsRef<Path>) -> Result<(), DocSenseError> {
+        self.model.lock().unwrap().remove_documents(path.as_ref()).map(|_| ())
+    }
+
+    /* Index every new or modified file below `dir_path` on `jobs` threads and drop the deleted ones.
+       Files parsed with other parse options than the current ones are indexed again too. */
+    pub fn index_directory(&self, dir_path: impl AsRef<Path>, jobs: usize, progress: &(dyn Fn(IndexEvent) + Sync)) -> Result<IndexSummary, DocSenseError> {
+        let stale = self.remove_stale_formats()?;
+        let mut summary = indexer::index_directory(dir_path.as_ref(), self.model(), &self.options, jobs, progress)?;
+        // Stale files which could not be indexed again are gone from the index
+        if !stale.is_empty() {
+            let indexed = self.paths()?.into_iter().collect::<HashSet<_>>();
+            summary.removed += stale.iter().filter(|path| !indexed.contains(*path)).count();
+        }
+        Ok(summary)
+    }
+
+    /* Remove the files whose format is parsed with other settings than it was when they were
+       indexed (e.g. tables indexed before `records` was turned on), and record the current ones.
+       Returns the paths of the removed files. */
+    fn remove_stale_formats(&self) -> Result<Vec<PathBuf>, DocSenseError> {
+        let mut model = self.model.lock().unwrap();
+        let (stored, current) = (model.format_settings()?, self.options.format_settings());
+        if stored == current {
+            return Ok(Vec::new());
+        }
+        let mut stale = Vec::new();
+        for path in model.indexed_paths()? {
+            let ext = inner_extension(&path).unwrap_or_default().to_lowercase();
+            if stored.get(&ext) != current.get(&ext) {
+                model.remove_documents(&path)?;
+                stale.push(path);
+            }
+        }
+        model.set_format_settings(current)?;
+        Ok(stale)
+    }
+
+    /* The best matches of a query, see the query module for its syntax */
+    pub fn search(&self, query: &str, options: &SearchOptions) -> Result<Vec<SearchResult>, DocSenseError> {
+        let query = query.chars().collect::<Vec<_>>();
+        self.model.lock().unwrap().search(&query, options.rank_method.clone(), options.limit)
+    }
+
+    /* Paths of all the indexed files */
+    pub fn paths(&self) -> Result<Vec<PathBuf>, DocSenseError> {
+        self.model.lock().unwrap().indexed_paths()
+    }
+
+    pub fn stats(&self) -> Result<Stats, DocSenseError> {
+        self.model.lock().unwrap().stats()
+    }
+
+    pub fn analysis(&self) -> Result<Analysis, DocSenseError> {
+        self.model.lock().unwrap().analysis()
+    }
+
+    /* Change how documents are broken up into terms. As documents analysed before would no longer
+       match the same queries, they are all removed when the settings differ from the current ones
+       and have to be indexed again. Returns whether that happened. */
+    pub fn set_analysis(&self, analysis: Analysis) -> Result<bool, DocSenseError> {
+        let mut model = self.model.lock().unwrap();
+        if model.analysis()? == analysis {
+            return Ok(false);
+        }
+        for path in model.indexed_paths()? {
+            model.remove_documents(&path)?;
+        }
+        model.set_analysis(analysis)?;
+        Ok(true)
+    }
+
+    /* Write every change to where the index is stored */
+    pub fn save(&self) -> Result<(), DocSenseError> {
+        match &self.path {
+            Some(path) => self.model.lock().unwrap().save(path),
+            None => Ok(()),
+        }
+    }
+
+    /* Copy every document into a new index at `output_path` stored as `backend`. Documents are
+       analysed again from their stored text and fields with the same settings, which yields the
+       very same terms and positions. */
+    pub fn convert(&self, output_path: impl AsRef<Path>, backend: Backend) -> Result<Index, DocSenseError> {
+        let output_path = output_path.as_ref();
+        if output_path.exists() {
+            return Err(DocSenseError::io(output_path, std::io::Error::new(std::io::ErrorKind::AlreadyExists, "the output file already exists")));
+        }
+        let target = Index::create(output_path, backend)?;
+        {
+            let source = self.model.lock().unwrap();
+            let mut model = target.model.lock().unwrap();
+            let analysis = source.analysis()?;
+            model.set_analysis(analysis)?;
+            model.set_format_settings(source.format_settings()?)?;
+            for path in source.indexed_paths()? {
+                let Some((documents, last_modified)) = source.stored_documents(&path)? else { continue };
+                let documents = documents.into_iter().map(|document| AnalyzedDocument::new(document, &analysis)).collect();
+                model.add_documents(path, documents, last_modified)?;
+            }
+        }
+        target.save()?;
+        Ok(target)
+    }
+}
+
+/* Just the version of a json index, which every version of the layout has */
+#[derive(serde::Deserialize)]
+struct Versioned {
+    #[serde(default)]
+    version: u32,
+}
+
+/* Fetch the InMemory model from a json or binary index file */
+fn fetch_model(index_path: &Path) -> Result<InMemoryModel, DocSenseError> {
+    let bytes = fs::read(index_path).map_err(|err| DocSenseError::io(index_path, err))?;
+
+    if binary::is_binary_index(&bytes) {
+        let mut model = binary::decode(&bytes, index_path)?;
+        model.format = FileFormat::Binary;
+        return Ok(model);
+    }
+
+    let corrupt = |err: serde_json::Error| DocSenseError::IndexCorrupt { path: index_path.to_path_buf(), reason: err.to_string() };
+
+    // Another version may be laid out differently, leave it to the caller to rebuild it
+    let Versioned { version } = serde_json::from_slice(&bytes).map_err(corrupt)?;
+    if version != INDEX_VERSION {
+        return Ok(InMemoryModel { version, ..Default::default() });
+    }
+
+    let model = serde_json::from_slice(&bytes).map_err(corrupt)?;
+
+    return Ok(model);
+}
+
+/* Load the model stored at `index_path` as `backend`, or an empty one if there is no file yet.
+   Also tells whether the stored index was built by an older version, in which case it is
+   only emptied when it may be written to. */
+fn load_model(index_path: &Path, backend: Backend, read_only: bool) -> Result<(Arc<Mutex<dyn Model + Send>>, bool), DocSenseError> {
+    let exists = index_path.exists();
+
+    match backend {
+        Backend::Json | Backend::Binary => {
+            let mut model: InMemoryModel = if exists { fetch_model(index_path)? } else { Default::default() };
+            let outdated = exists && model.version != INDEX_VERSION;
+            if outdated && !read_only {
+                model = Default::default();
+            }
+            model.format = if backend == Backend::Binary { FileFormat::Binary } else { FileFormat::Json };
+            Ok((Arc::new(Mutex::new(model)), outdated))
+        }
+        Backend::Sqlite => {
+            let mut model = SqliteModel::open(index_path)?;
+            let outdated = model.version()? != INDEX_VERSION;
+            if outdated && !read_only {
+                model.reset()?;
+            }
+            Ok((Arc::new(Mutex::new(model)), outdated))
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, Arc, Mutex},
+    thread,
+    time::SystemTime,
+};
+
+use super::error::DocSenseError;
+use super::model::{AnalyzedDocument, Model};
+use super::parsers::{is_indexable, member_path, parse_file_by_ext, ParseOptions};
+
+/* Progress of an indexing run, passed to the caller while it happens */
+pub enum IndexEvent<'a> {
+    /* A file is being parsed and added to the index */
+    Indexing(&'a Path),
+    /* A file was left out as there is no parser for it */
+    Unsupported(&'a Path),
+    /* A file is gone and was dropped from the index */
+    Removed(&'a Path),
+}
+
+/* What a run over the files of a directory changed in the model */
+#[derive(Default)]
+pub struct IndexSummary {
+    pub indexed: usize,
+    pub removed: usize,
+    pub skipped: Vec<DocSenseError>,    // Files or archive members which could not be parsed, they are left out of the index
+    pub lossy: Vec<PathBuf>,            // Files or archive members with text which could not be decoded in full, it was replaced
+}
+
+/* Walks a folder recursively and collects every supported file the model has no up to date copy of into `pending` */
+pub fn append_folder_to_model(dir_path: &Path, model: Arc<Mutex<dyn Model + Send>>, options: &ParseOptions, pending: &mut Vec<(PathBuf, SystemTime)>, visited: &mut HashSet<PathBuf>, progress: &(dyn Fn(IndexEvent) + Sync)) -> Result<(), DocSenseError> {
+    let dir = fs::read_dir(dir_path).map_err(|err| DocSenseError::io(dir_path, err))?;
+
+    'step: for file in dir {
+        let file = file.map_err(|err| DocSenseError::io(dir_path, err))?;
+
+        let file_path = file.path();
+
+        let last_modified = file.metadata()
+            .and_then(|metadata| metadata.modified())
+            .map_err(|err| DocSenseError::io(&file_path, err))?;
+
+        // Skip dot files or folders, before anything is read from them
+        let dot_files = file.file_name().to_string_lossy().starts_with('.');
+        if dot_files {
+            continue 'step;
+        }
+
+        // Skip unsupported files, those without an extension are told by their contents
+        if !file_path.is_dir() && !is_indexable(&file_path, options) {
+            progress(IndexEvent::Unsupported(&file_path));
+            continue 'step;
+        }
+
+        // If file is another directory recursively index it too
+        let file_type = file.file_type().map_err(|err| DocSenseError::io(&file_path, err))?;
+
+        // Recursively index all the folders
+        if file_type.is_dir() {
+            append_folder_to_model(&file_path, Arc::clone(&model), options, pending, visited, progress)?;
+            continue 'step;
+        }
+
+        // Get absolute file path
+        let file_path = fs::canonicalize(&file_path).map_err(|err| DocSenseError::io(&file_path, err))?;
+
+        visited.insert(file_path.clone());
+
+        // Parsing is left to the workers of `index_files`
+        if model.lock().unwrap().requires_reindexing(&file_path, last_modified)? {
+            pending.push((file_path, last_modified));
+        }
+    }
+    Ok(())
+}
+
+/* Parse and tokenize the files on `jobs` worker threads. The model is only locked to merge
+   the documents of a finished file into it. Files that fail to parse are skipped and reported in the summary,
+   what was indexed of them before is removed. */
+pub fn index_files(files: &[(PathBuf, SystemTime)], model: Arc<Mutex<dyn Model + Send>>, options: &ParseOptions, jobs: usize, progress: &(dyn Fn(IndexEvent) + Sync)) -> Result<IndexSummary, DocSenseError> {
+    let next = AtomicUsize::new(0);
+    let summary = Mutex::new(IndexSummary::default());
+    let failure = Mutex::new(None);
+    let failed = AtomicBool::new(false);
+    let analysis = model.lock().unwrap().analysis()?;
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, files.len().max(1)) {
+            scope.spawn(|| {
+                // Every worker grabs the next file nobody has taken yet
+                while !failed.load(Ordering::Relaxed) {
+                    let Some((file_path, last_modified)) = files.get(next.fetch_add(1, Ordering::Relaxed)) else { break };
+                    progress(IndexEvent::Indexing(file_path));
+
+                    let documents = match parse_file_by_ext(file_path, options) {
+                        Ok(parsed) => {
+                            // Members of an archive which failed are reported, the rest of it is still indexed
+                            let mut lossy = parsed.documents.iter()
+                                .filter(|document| document.lossy)
+                                .map(|document| document.member.as_ref().map_or_else(|| file_path.clone(), |member| member_path(file_path, member)))
+                                .collect::<Vec<_>>();
+                            lossy.dedup();
+                            let mut summary = summary.lock().unwrap();
+                            summary.skipped.extend(parsed.skipped);
+                            summary.lossy.extend(lossy);
+                            drop(summary);
+                            Some(parsed.documents.into_iter().map(|document| AnalyzedDocument::new(document, &analysis)).collect())
+                        }
+                        Err(err) => {
+                            summary.lock().unwrap().skipped.push(err);
+                            None
+                        }
+                    };
+
+                    // A file which fails to parse keeps nothing of an earlier version of it indexed
+                    let merged = match documents {
+                        Some(documents) => model.lock().unwrap().add_documents(file_path.clone(), documents, *last_modified).map(|()| (1, 0)),
+                        None => model.lock().unwrap().remove_documents(file_path).map(|removed| (0, usize::from(removed))),
+                    };
+
+                    // A failing model would fail for every other file too, so stop all workers
+                    match merged {
+                        Ok((indexed, removed)) => {
+                            let mut summary = summary.lock().unwrap();
+                            summary.indexed += indexed;
+                            summary.removed += removed;
+                        }
+                        Err(err) => {
+                            failure.lock().unwrap().get_or_insert(err);
+                            failed.store(true, Ordering::Relaxed);
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    if let Some(err) = failure.into_inner().unwrap() {
+        return Err(err);
+    }
+    Ok(summary.into_inner().unwrap())
+}
+
+/* Bring the model up to date with a directory: new and modified files are indexed, deleted
+   ones removed. Saving the model afterwards is left to the caller. */
+pub fn index_directory(dir_path: &Path, model: Arc<Mutex<dyn Model + Send>>, options: &ParseOptions, jobs: usize, progress: &(dyn Fn(IndexEvent) + Sync)) -> Result<IndexSummary, DocSenseError> {
+    let root_dir = fs::canonicalize(dir_path).map_err(|err| DocSenseError::io(dir_path, err))?;
+
+    let mut pending = Vec::new();
+    let mut visited: HashSet<PathBuf> = HashSet::new();
+
+    append_folder_to_model(&root_dir, Arc::clone(&model), options, &mut pending, &mut visited, progress)?;
+    let mut summary = index_files(&pending, Arc::clone(&model), options, jobs, progress)?;
+
+    // Purge deleted files
+    let mut model_lock = model.lock().unwrap();
+    let mut to_remove = Vec::new();
+
+    for path in model_lock.indexed_paths()? {
+        if path.starts_with(&root_dir) && !visited.contains(&path) {
+            to_remove.push(path);
+        }
+    }
+
+    for path in to_remove {
+        model_lock.remove_documents(&path)?;
+        progress(IndexEvent::Removed(&path));
+        summary.removed += 1;
+    }
+    Ok(summary)
+}
@@ -1,4 +1,5 @@
 use rust_stemmers::{Algorithm, Stemmer};
+use std::{collections::VecDeque, ops::Range};
 
 const STOP_WORDS: &[&str] = &[
     "A", "AN", "THE",
@@ -9,14 +10,73 @@ const STOP_WORDS: &[&str] = &[
     "NOT", "NO",
 ];
 
+/* Terms of a document field are indexed as `field:TERM`, apart from the terms of its text.
+   Field names are lower case while terms are upper case, so the two never collide. */
+pub fn field_term(field: &str, term: &str) -> String {
+    format!("{field}:{term}")
+}
+
+/* Whether `name` can be a field, which are named in lower case letters, digits and underscores
+   starting with a letter, e.g. `address_line_2` */
+pub fn is_field_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/* The field a term belongs to, if any, and the bare term */
+pub fn split_field(term: &str) -> (Option<&str>, &str) {
+    match term.split_once(':') {
+        Some((field, bare)) if is_field_name(field) => (Some(field), bare),
+        _ => (None, term),
+    }
+}
+
+/* A token along with where it was found in the lexed content */
+#[derive(Debug)]
+pub struct Token {
+    pub term: String,
+    pub position: usize,        // Index of the lexeme, see `Lexer::position`
+    pub span: Range<usize>,     // Char offsets of the original (unstemmed) text
+}
+
 #[derive(Debug)]
 pub struct Lexer<'a> {
-    content: &'a [char]
+    content: &'a [char],
+    position: usize,    // Index of the next lexeme. Skipped stop words still take up a position.
+    length: usize,      // Length of the whole content, to turn what is left of it into offsets
+    code: bool,         // Split identifiers into their words, see `code`
+    stem: bool,
+    pending: VecDeque<Token>,   // Words of the identifier lexed last, not handed out yet
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(content: &'a [char]) -> Self {
-        Self { content }
+        Self { content, position: 0, length: content.len(), code: false, stem: true, pending: VecDeque::new() }
+    }
+
+    /* Lex the content as source code: an identifier like `parseXmlFile` or `parse_xml_file` yields
+       the whole identifier as PARSEXMLFILE, at the position of its first word, followed by each of
+       its words. */
+    pub fn code(mut self) -> Self {
+        self.code = true;
+        self
+    }
+
+    /* Whether words are reduced to their English stem, e.g. RUNNING to RUN. Without stemming
+       words only match as they are written, which suits the identifiers of source code. */
+    pub fn stemmed(mut self, stem: bool) -> Self {
+        self.stem = stem;
+        self
+    }
+
+    /* Iterate over the tokens together with their position in the content */
+    pub fn positioned(self) -> impl Iterator<Item = (usize, String)> + 'a {
+        self.tokens().map(|token| (token.position, token.term))
+    }
+
+    /* Iterate over the tokens together with their position and span in the content */
+    pub fn tokens(mut self) -> impl Iterator<Item = Token> + 'a {
+        std::iter::from_fn(move || self.next_token())
     }
 
     fn trim_left(&mut self) {
@@ -46,8 +106,18 @@ impl<'a> Lexer<'a> {
         return self.chop(n);
     }
 
-    fn next_token(&mut self) -> Option<String> {
+    fn next_token(&mut self) -> Option<Token> {
+        if let Some(token) = self.pending.pop_front() {
+            return Some(token);
+        }
+
         let stemmer = Stemmer::create(Algorithm::English);
+        // The stemmer only understands lowercase words, so words are lowered before stemming
+        let stem = self.stem;
+        let term = |word: &str| match stem {
+            true => stemmer.stem(&word.to_lowercase()).into_owned().to_uppercase(),
+            false => word.to_uppercase(),
+        };
         loop {
             self.trim_left();
 
@@ -55,33 +125,93 @@ impl<'a> Lexer<'a> {
                 return None;
             }
 
+            let position = self.position;
+            let start = self.length - self.content.len();
+            self.position += 1;
+
             if self.content[0].is_numeric() {
                 // Ignore single digit number 
                 let result = self.chop_while(|x| x.is_numeric());
                 if result.len() == 1 { continue; }
-                return Some(result.iter().collect());
+                return Some(Token { term: result.iter().collect(), position, span: start..start + result.len() });
+            }
+
+            if self.code && (self.content[0].is_alphabetic() || self.content[0] == '_') {
+                let result = self.chop_while(|x| x.is_alphanumeric() || *x == '_');
+                let words = split_identifier(result);
+                let Some(first) = words.first() else { continue };
+
+                if words.len() > 1 {
+                    let whole = words.iter().map(|word| result[word.clone()].iter().collect::<String>()).collect::<String>();
+                    self.pending.push_back(Token { term: term(&whole), position, span: start + first.start..start + result.len() });
+                }
+                // Underscores take up a position, same as punctuation in prose, so a query for
+                // `parse_xml_file` finds the words it is split into as a phrase
+                let mut offset = 0;
+                for (i, word) in words.iter().enumerate() {
+                    if i > 0 && result[words[i - 1].end..word.start].contains(&'_') {
+                        offset += 1;
+                    }
+                    let word_term = term(&result[word.clone()].iter().collect::<String>());
+                    if !STOP_WORDS.contains(&word_term.as_str()) {
+                        self.pending.push_back(Token { term: word_term, position: position + offset, span: start + word.start..start + word.end });
+                    }
+                    offset += 1;
+                }
+                self.position = position + offset;
+                match self.pending.pop_front() {
+                    Some(token) => return Some(token),
+                    None => continue,
+                }
             }
 
             if self.content[0].is_alphabetic() {
                 let result = self.chop_while(|x| x.is_alphanumeric());
-                let token = result.iter().collect::<String>();
-
-                let stemmed = stemmer.stem(&token).into_owned().to_uppercase();
-                if STOP_WORDS.contains(&stemmed.as_str()) {
+                let token = term(&result.iter().collect::<String>());
+                if STOP_WORDS.contains(&token.as_str()) {
                     continue;
                 }
-                return Some(stemmed);
+                return Some(Token { term: token, position, span: start..start + result.len() });
             }
             
             let token = self.chop(1);
-            return Some(token.iter().collect());
+            return Some(Token { term: token.iter().collect(), position, span: start..start + 1 });
         }
     }
 }
 
+/* Char ranges of the words of an identifier, split at underscores and where the case changes.
+   An acronym ends before the capital starting the next word, e.g. `XMLFile` is XML and File. */
+fn split_identifier(identifier: &[char]) -> Vec<Range<usize>> {
+    let mut words = Vec::new();
+    let mut start = 0;
+    for i in 0..identifier.len() {
+        let c = identifier[i];
+        if c == '_' {
+            if start < i {
+                words.push(start..i);
+            }
+            start = i + 1;
+            continue;
+        }
+        if i > start && c.is_uppercase() {
+            let previous = identifier[i - 1];
+            let next_lower = identifier.get(i + 1).is_some_and(|next| next.is_lowercase());
+            if previous.is_lowercase() || previous.is_numeric() || (previous.is_uppercase() && next_lower) {
+                words.push(start..i);
+                start = i;
+            }
+        }
+    }
+    if start < identifier.len() {
+        words.push(start..identifier.len());
+    }
+    words
+}
+
 impl<'a> Iterator for Lexer<'a> {
     type Item = String;
     fn next(&mut self) -> Option<<Self as Iterator>::Item> { 
-        self.next_token()
+        self.next_token().map(|token| token.term)
     }
 }
\ No newline at end of file
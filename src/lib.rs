@@ -0,0 +1,21 @@
+/* DocSense as a library: build or open an index, add documents to it and search it.
+   `Index` covers the common uses, the modules below it are there for finer control. */
+
+pub mod config;
+pub mod error;
+pub mod index;
+pub mod indexer;
+pub mod lexer;
+pub mod model;
+pub mod parsers;
+pub mod query;
+pub mod snippet;
+mod binary;
+
+pub use config::Config;
+pub use error::DocSenseError;
+pub use index::{Backend, Index, SearchOptions};
+pub use indexer::{IndexEvent, IndexSummary};
+pub use model::{Analysis, RankMethod, SearchResult, Stats};
+pub use parsers::{Fields, ParseOptions, ParsedDocument, ParsedFile, TextKind};
+pub use snippet::Snippet;
@@ -1,362 +1,226 @@
 use std:: {
-    collections::HashSet, fs::{self, File}, io::{self, BufReader, BufWriter, Read}, path::{Path, PathBuf}, process::{exit, ExitCode}, str::{self}, sync::{Arc, Mutex}, thread
+    fs, num::NonZeroUsize, path::Path, process::ExitCode, thread
 };
 
 use parser::{Cli, Commands};
-use clap::{Parser, ValueEnum};
-use xml::{self, reader::XmlEvent, EventReader};
-use xml::common::{TextPosition, Position};
+use clap::Parser;
 use colored::{Colorize};
+use docsense::{config::CONFIG_FILE_NAME, Analysis, Backend, Config, DocSenseError, Index, IndexEvent, IndexSummary, ParseOptions, SearchOptions};
 
 mod parser;
-mod lexer;
 mod server;
-mod model;
+mod watcher;
 mod benchmark;
 
-use crate::model::*;
-use poppler::{Document};
-
-/* Parse all the text (Character Events) from the XML File */
-fn parse_xml_file(file_path: &Path) -> Result<String, ()> {
-    let file = File::open(file_path).map_err(|err| {
-        eprintln!("{}: Could not open file {file_path}: {err}", "ERROR".bold().red(), file_path = file_path.display());
-    })?;
-    let er = EventReader::new(BufReader::new(file));
-    let mut content = String::new();
-
-    for event in er.into_iter() {
-        let event = event.map_err(|err| {
-            let TextPosition {row, column} = err.position();
-            let msg = err.msg();
-            eprintln!("{file_path}:{row}:{column}: {err}: {msg}", err = "ERROR".red(), 
-                                                                file_path = file_path.display());
-        })?;
-
-        if let XmlEvent::Characters(text) = event {
-            content.push_str(&text);
-            content.push(' ');
-        }
+/* Print the progress of an indexing run as it goes */
+pub(crate) fn report_progress(event: IndexEvent) {
+    match event {
+        IndexEvent::Indexing(path) => println!("{}: Indexing {} ...", "INFO".cyan(), path.display().to_string().bright_cyan()),
+        IndexEvent::Unsupported(path) => println!("{}: Skipping unsupported file {}", "INFO".cyan(), path.display().to_string().bright_yellow()),
+        IndexEvent::Removed(path) => println!("{}: Removed deleted file {}", "INFO".cyan(), path.display().to_string().bright_yellow()),
     }
-    Ok(content)
-}
-
-/* Parse all the text from the TXT File */
-fn parse_txt_file(file_path: &Path) -> Result<String, ()> {
-    let mut content = String::new();
-    let mut file = File::open(file_path).map_err(|err| {
-        eprintln!("{}: Could not open file {path} as {err}", "ERROR".bold().red(), path = file_path.to_string_lossy().bright_blue(), err = err.to_string().red());
-    })?;
-
-    let _ = file.read_to_string(&mut content);
-    Ok(content)
 }
 
-/* Parse all the text from the PDF File with Poppler */
-fn parse_pdf_files(file_path: &Path) -> Result<String, ()> {
-    let mut file_content = Vec::new();
-
-    File::open(file_path)
-        .and_then(|mut file| file.read_to_end(&mut file_content))
-        .map_err(|err| {
-            eprintln!("{}: Failed to get contents of PDF file {path} as {err}", "ERROR".red().bold(), path = file_path.to_string_lossy().bright_blue(), err = err.to_string().red());
-        })?;
-
-
-    let pdf_file = Document::from_data(&file_content, None).map_err(|err| {
-        eprintln!("{}: Failed to make poppler document out of PDF file {path} as {err}", "ERROR".red().bold(), path = file_path.to_string_lossy().bright_blue(), err = err.to_string().red());
-    })?;
-
-    let mut content = String::new();
-    if pdf_file.n_pages() > 0 {
-        for i in 0..pdf_file.n_pages() { 
-            if let Some(page) = pdf_file.page(i) {
-                if let Some(text) = page.text() {
-                    content.push_str(&text.as_str());
-                }
-            } else {
-                eprintln!("{}: Could not get text of page {i} of {file_path}.", "ERROR".red().bold(), file_path = file_path.to_string_lossy().bright_blue())
-            }
-        }
+/* Tell about the files an indexing run had to leave out, and those it could not decode in full */
+pub(crate) fn report_skipped(summary: &IndexSummary) {
+    for err in &summary.skipped {
+        eprintln!("{}: Skipped file as {err}", "ERROR".bold().red(), err = err.to_string().red());
     }
-    Ok(content)
-}
-
-fn parse_file_by_ext(file_path: &Path) -> Result<String, ()> {
-    let ext = file_path.extension().ok_or_else(|| {
-        eprintln!("{}: Could not get extension of {path}", "ERROR".bold().red(), path = file_path.to_string_lossy().bright_blue());   
-    })?.to_str();
-
-    match ext.unwrap() {
-        "xml" | "xhtml" => {
-            return parse_xml_file(file_path);
-        }
-
-        "txt" | "md" => {
-            return parse_txt_file(file_path); 
-        }
-
-        "pdf" => {
-            return parse_pdf_files(file_path);
-        }
-
-        _ => {
-            eprintln!("{}: Extension {ext} is unsupported", "ERROR".bold().red(), ext = ext.unwrap());   
-            Err(())
+    if !summary.lossy.is_empty() {
+        let files = if summary.lossy.len() == 1 { "file" } else { "files" };
+        eprintln!("{}: {count} {files} held characters which could not be decoded, they were replaced", "WARNING".bold().yellow(), count = summary.lossy.len());
+        for path in &summary.lossy {
+            eprintln!("    {}", path.display().to_string().bright_yellow());
         }
     }
 }
 
-/* Save the model to a path as json file */
-fn save_model_as_json(model: &InMemoryModel, index_path: &str) -> Result<(), ()> {
-    println!("Saving {} ...", index_path.bright_blue());
-    
-    let index_file = File::create(index_path).map_err(|err| {
-        eprintln!("{}: Failed to create file {path} as {err}", "ERROR".bold().red(), path = index_path.bright_blue(), err = err.to_string().red());
-    })?;
-
-    serde_json::to_writer(BufWriter::new(index_file), &model).unwrap_or_else(|err| {
-        eprintln!("{}: Failed to save file {path} as {err}", "ERROR".bold().red(), path = index_path.bright_blue(), err = err.to_string().red());
-    });
-    Ok(())
+/* Number of indexing workers, all available CPUs unless set with --jobs */
+fn worker_count(jobs: Option<usize>) -> usize {
+    jobs.unwrap_or_else(|| thread::available_parallelism().map(NonZeroUsize::get).unwrap_or(1)).max(1)
 }
 
-const ALLOWED_FILE_TYPE_EXTENSIONS: [&str; 5] = ["xml", "xhtml", "txt", "md", "pdf"];
-
-/* Indexes a folder as a json file and adds to model, Processed is the number of file indexed */
-fn append_folder_to_model(dir_path: &Path, model: Arc<Mutex<InMemoryModel>>, processed: &mut usize, visited: &mut HashSet<PathBuf>) -> Result<(), ()> {
-    let dir = fs::read_dir(dir_path).map_err(|err| {
-        eprintln!("{}: Failed to read directory {dir_path} as \"{err}\"", "ERROR".bold().red(), 
-                                                                            dir_path = dir_path.to_str().unwrap().bold().bright_blue(), 
-                                                                            err = err.to_string().red());
-        exit(-1);
-    })?;
-
-    'step: for file in dir {
-        let file = file.map_err(|err| {
-            eprintln!("{}: Failed to read next file in directory {dir_path} as {err}", "ERROR".bold().red(), 
-                                                                                        dir_path = dir_path.to_str().unwrap().bold().bright_blue(), 
-                                                                                        err = err.to_string().red());
-        })?;
-
-        let file_path = file.path();
-        let file_path_str = file_path.to_str().unwrap();
-        
-        let last_modified = file.metadata().map_err(|err| {
-            eprintln!("{}: Failed to get metadata of file {path} as {err}", "ERROR".bold().red(), path = file_path_str.bright_blue(), err = err.to_string().red());
-        })?.modified().map_err(|err| {
-            eprintln!("{}: Failed to last modified time of file {path} as {err}", "ERROR".bold().red(), path = file_path_str.bright_blue(), err = err.to_string().red());
-        }).unwrap();
-        
-        let file_ext = file_path.extension();
-
-        // Skip unsupported files
-        if let Some(ext) = file_ext {
-            if !ALLOWED_FILE_TYPE_EXTENSIONS.contains(&ext.to_str().unwrap()) {
-                println!("{}: Skipping unsupported file {}", "INFO".cyan(), file_path_str.bright_yellow());
-                continue 'step;
-            }
-        }
-
-        // Skip dot files or folders 
-        let dot_files = file_path
-                              .file_name()
-                              .and_then(|s| s.to_str())
-                              .map(|s| s.starts_with("."))
-                              .unwrap();
-        if dot_files {
-            continue 'step;
-        }
-
-        // If file is another directory recursively index it too 
-        let file_type = file.file_type().map_err(|err| {
-            eprintln!("{err}: Failed to determine file type for {file_path} as {msg}", err = "ERROR".bold().red(), file_path = file_path_str.bold().bright_blue(), msg = err.to_string().red());
-        })?;
-
-        // Recursively index all the folders
-        if file_type.is_dir() {
-            append_folder_to_model(&file_path, Arc::clone(&model), processed, visited)?;
-            continue 'step;
-        }   
-
-        // Get absolute file path 
-        let file_path = fs::canonicalize(&file_path).map_err(|err| {
-            eprintln!("{}: Could not canonicalize path {} as {}", "ERROR".red().bold(),
-            file_path.display().to_string().bright_blue(),
-            err.to_string().red());
-        })?;
+pub(crate) fn save_index(index: &Index) -> Result<(), DocSenseError> {
+    if let Some(index_path) = index.path() {
+        println!("Saving {} ...", index_path.display().to_string().bright_blue());
+    }
+    index.save()
+}
 
-        visited.insert(file_path.clone());
-        
-        // Main logic
-        let mut model = model.lock().unwrap();
-        if model.requires_reindexing(&file_path, last_modified)? {
-            println!("{}: Indexing {} ...", "INFO".cyan(), file_path_str.bright_cyan());
-    
-            let content = match parse_file_by_ext(&file_path) {
-                Ok(content) => content.chars().collect::<Vec<_>>(),
-                Err(_) => {
-                    eprintln!("{}: Failed to read xml file {path}", "ERROR".bold().red(), path = file_path.to_str().unwrap().bright_blue());
-                    continue 'step;
-                }
-            };
+/* Bring the index up to date with a directory, saving it when anything changed */
+pub(crate) fn index_directory(index: &Index, dir_path: &Path, jobs: usize) -> Result<IndexSummary, DocSenseError> {
+    let summary = index.index_directory(dir_path, jobs, &report_progress)?;
 
-            model.add_document(file_path, &content, last_modified)?;
-            *processed += 1;
-        }  
+    // Save the model only when some files were added/modified/deleted
+    if summary.indexed + summary.removed > 0 {
+        save_index(index)?;
     }
-    Ok(())
+    println!("{}: Finished indexing ...", "INFO".cyan());
+    Ok(summary)
 }
 
 /* Check the amount of files present in the main frequency table index */
-fn check_index(index_path: &str) -> Result<(), ()> {
-    let index_file = fs::File::open(index_path).map_err(|err| {
-        eprintln!("{}: Could not open file {file} as \"{err}\"", "ERROR".bold().red(), file = index_path.bright_blue(), err = err.to_string().red());
-        exit(1);
-    })?;
-
+fn check_index(index_path: &str) -> Result<(), DocSenseError> {
     println!("{info}: Reading file {file}", info = "INFO".cyan(), file = index_path.bright_blue());
-    let model: InMemoryModel = serde_json::from_reader(BufReader::new(index_file)).map_err(|err|  {
-        eprintln!("{}: Serde could not read file {file} as \"{err}\"", "ERROR".bold().red(), file = index_path.bright_blue(), err = err.to_string().red());
-        exit(1);
-    })?;
-    println!("{info}: Index file has {entries} entries", info = "INFO".cyan(), entries = model.docs.len());
+    let index = Index::open(index_path)?;
+    let backend = Backend::detect(Path::new(index_path))?;
+    let stats = index.stats()?;
+    println!("{info}: Index file is stored as {backend:?} and has {entries} entries", info = "INFO".cyan(), entries = stats.doc_count);
     Ok(())  
 }
 
-/* Fetch the InMemory model from an index file */
-fn fetch_model(index_path: &str) -> Result<InMemoryModel, ()> {
-    let index_file = fs::File::open(&index_path).map_err(|err| {
-        eprintln!("{}: Could not open file {file_path} as \"{err}\"", "ERROR".bold().red(), file_path = index_path.bright_blue(), err = err.to_string().red());
-    })?;
-
-    let model = serde_json::from_reader(BufReader::new(index_file)).map_err(|err| {
-        eprintln!("{}: Serde failed to read {file_path} as \"{err}\"", "ERROR".bold().red(), file_path = index_path.bright_blue(), err = err.to_string().red());
-    })?;
+/* Copy every document of the index at `input` into a new index at `output` stored as `backend` */
+fn convert_index(input: &str, output: &str, backend: Backend) -> Result<(), DocSenseError> {
+    let converted = Index::open(input)?.convert(output, backend)?.stats()?.doc_count;
+    println!("{}: Converted {converted} documents from {input} to {output}", "INFO".cyan(), input = input.bright_blue(), output = output.bright_blue());
+    Ok(())
+}
 
-    return Ok(model);
+/* Apply the --stem-code option to an index, which drops every document when it changes the setting */
+fn apply_stem_code(index: &Index, stem_code: Option<bool>) -> Result<(), DocSenseError> {
+    let Some(stem_code) = stem_code else { return Ok(()) };
+    if index.set_analysis(Analysis { stem_code })? {
+        println!("{}: Stemming of source code changed, every file is indexed again", "INFO".cyan());
+    }
+    Ok(())
 }
 
-pub(crate) fn index_directory(dir_path: &Path, model: Arc<Mutex<InMemoryModel>>, index_path: Option<&str>) -> Result<(), ()> {
-    let root_dir = fs::canonicalize(dir_path).unwrap_or_else(|err| {
-        eprintln!("{}: Could not canonicalize root dir {} as {}", "ERROR".bold().red(), dir_path.display().to_string().bright_blue(), err.to_string().red());
-        exit(1);
-    });
+/* Storage format of the index at `index_path`: the --backend asked for, or else the one it is
+   stored in already, JSON for a new index */
+fn index_backend(index_path: &Path, backend: Option<Backend>) -> Result<Backend, DocSenseError> {
+    match backend {
+        Some(backend) => Ok(backend),
+        None if index_path.exists() => Backend::detect(index_path),
+        None => Ok(Backend::Json),
+    }
+}
 
-    let mut processed: usize = 0;
-    let mut visited: HashSet<PathBuf> = HashSet::new();
-    
-    append_folder_to_model(&root_dir, Arc::clone(&model), &mut processed, &mut visited)?;
-    
-    // Purge deleted files
-    let mut model_lock = model.lock().unwrap();
-    let mut to_remove = Vec::new();
-    
-    for path in model_lock.docs.keys() {
-        if path.starts_with(&root_dir) && !visited.contains(path) {
-            to_remove.push(path.clone());
+/* Options to parse files with from the --config file, or the config file of the indexed directory without its converters */
+fn load_config(dir_path: &str, config: Option<String>) -> Result<ParseOptions, DocSenseError> {
+    let config = match config {
+        Some(config_path) => {
+            let config_path = Path::new(&config_path);
+            if !config_path.exists() {
+                return Err(DocSenseError::io(config_path, std::io::ErrorKind::NotFound.into()));
+            }
+            Config::load(config_path)?
         }
+        None => Config::load_for_directory(Path::new(dir_path))?,
+    };
+    if config.ignored_converters > 0 {
+        let config_path = Path::new(dir_path).join(CONFIG_FILE_NAME);
+        println!("{}: Ignoring the converters of {}, pass it with --config to run them", "WARNING".bold().yellow(), config_path.display().to_string().bright_blue());
     }
-    
-    for path in to_remove {
-        model_lock.remove_document(&path);
-        println!("{}: Removed deleted file {}", "INFO".cyan(), path.display().to_string().bright_yellow());
-        processed += 1;
+    if !config.converters.is_empty() {
+        println!("{}: Using {} external converters", "INFO".cyan(), config.converters.len());
     }
-
-    // Save the model only when some files were added/modified/deleted
-    if processed > 0 {
-        if let Some(p) = index_path {
-            save_model_as_json(&model_lock, p)?;
-        }
+    if config.data.records {
+        println!("{}: Indexing every row and record of data files on its own", "INFO".cyan());
     }
-    println!("{}: Finished indexing ...", "INFO".cyan());
-    Ok(())
-}
-
-#[derive(ValueEnum, Clone, Debug, PartialEq)]
-pub(crate) enum RankMethod {
-    Tfidf, 
-    Bm25
+    Ok(config.parse_options())
 }
 
-fn entry() -> Result<(), ()> {
+fn entry() -> Result<(), DocSenseError> {
     let cli = Cli::parse();
 
     match cli.command {
         Commands::Search {index_file_path, prompt, rank_method} => {
-            let prompt = prompt.chars().collect::<Vec<char>>();
-            let model = fetch_model(&index_file_path)?;
-            for (path, rank) in model.search_query(&prompt, &model, rank_method)?.iter().take(20) {
-                println!("{path} - {rank}", path = path.display());
+            let index = Index::open(&index_file_path)?;
+            for result in index.search(&prompt, &SearchOptions { rank_method, limit: 20 })? {
+                match &result.locator {
+                    Some(locator) => println!("{path}#{locator} - {rank}", path = result.path.display(), rank = result.rank),
+                    None => println!("{path} - {rank}", path = result.path.display(), rank = result.rank),
+                }
+                // A chapter of a book is listed along with the title of the book, a message by its subject
+                let title = result.fields.get("title").or(result.fields.get("subject"));
+                let heading = title.into_iter().chain(result.fields.get("chapter")).map(String::as_str).collect::<Vec<_>>();
+                if !heading.is_empty() {
+                    println!("    {}", heading.join(" — ").bold());
+                }
+                for snippet in &result.snippets {
+                    println!("    {}", snippet.to_colored_string());
+                }
             } 
 
             return Ok(());
         }
 
         Commands::Check { index_file_path } => {
-            check_index(&index_file_path).unwrap();
+            check_index(&index_file_path)?;
+        }
+
+        Commands::Convert { input_path, output_path, to } => {
+            convert_index(&input_path, &output_path, to)?;
         }
 
-        Commands::Index { dir_path, output_file } => {
-            let model = Arc::new(Mutex::new(Default::default()));
+        Commands::Index { dir_path, output_file, backend, jobs, stem_code, config } => {
+            let options = load_config(&dir_path, config)?;
             let output_path = output_file.unwrap_or_else(|| {
                 let mut p = Path::new(&dir_path).to_path_buf();
-                p.push(".docsense.json");
+                p.push(backend.unwrap_or(Backend::Json).default_index_name());
                 p.to_str().unwrap().to_string()
             });
-            index_directory(Path::new(&dir_path), model, Some(&output_path))?;
+            let backend = index_backend(Path::new(&output_path), backend)?;
+            let index = Index::create(&output_path, backend)?.with_parse_options(options);
+            apply_stem_code(&index, stem_code)?;
+            let summary = index_directory(&index, Path::new(&dir_path), worker_count(jobs))?;
+            report_skipped(&summary);
         }
 
-        Commands::Serve { dir_path, address , rank_method} => {
+        Commands::Serve { dir_path, address , rank_method, backend, jobs, stem_code, config } => {
+            let options = load_config(&dir_path, config)?;
             // IDEATE: Is it fine to place the index file in the folder itself or place in a root dir?
             let mut index_path = Path::new(&dir_path).to_path_buf(); 
-            index_path.push(".docsense.json");
+            index_path.push(backend.unwrap_or(Backend::Json).default_index_name());
 
-            let model: Arc<Mutex<InMemoryModel>>;
-            if index_path.exists() {
-                // Fetch already existing model 
-                model = Arc::new(Mutex::new(fetch_model(&index_path.to_str().unwrap()).unwrap_or_else(|()| {
-                    eprintln!("{}: Failed to fetch model for {}.", "ERROR".bold().red(), index_path.to_string_lossy().bright_blue());
-                    exit(1);
-                })));
-            } else {
-                // Create a new model if not present 
-                model = Arc::new(Mutex::new(Default::default()));
-            }
-            
-            let root_dir = fs::canonicalize(&dir_path).unwrap_or_else(|err| {
-                eprintln!("{}: Could not canonicalize root dir {dir_path} as {err}", "ERROR".bold().red(), dir_path = dir_path.bright_blue(), err = err.to_string().red());
-                exit(1);
-            });
+            // Fetch already existing model or create a new one if not present
+            let backend = index_backend(&index_path, backend)?;
+            let index = Index::create(&index_path, backend)?.with_parse_options(options);
+            apply_stem_code(&index, stem_code)?;
+            let root_dir = fs::canonicalize(&dir_path).map_err(|err| DocSenseError::io(Path::new(&dir_path), err))?;
 
             {
-                let model = Arc::clone(&model);
+                let index = index.clone();
                 let dir_path = dir_path.clone();
+                let root_dir = root_dir.clone();
                 thread::spawn(move || {
-                    let index_str = index_path.to_str().unwrap().to_string();
-                    index_directory(Path::new(&dir_path), model, Some(&index_str)).unwrap();
+                    // Watch before indexing so nothing changed in the meantime is missed
+                    let watcher = watcher::DirectoryWatcher::new(&root_dir);
+                    match index_directory(&index, Path::new(&dir_path), worker_count(jobs)) {
+                        Ok(summary) => report_skipped(&summary),
+                        Err(err) => eprintln!("{}: {err}", "ERROR".bold().red()),
+                    }
+                    match watcher {
+                        Ok(watcher) => watcher.run(index, worker_count(jobs)),
+                        Err(err) => eprintln!("{}: {err}", "ERROR".bold().red()),
+                    }
                 });
             }
             // TODO: Print the information of server start at the end of logging
-            return server::start(&address, Arc::clone(&model), rank_method, root_dir);
+            return server::start(&address, index, rank_method, root_dir);
         }   
 
-        Commands::Benchmark { dir_path } => {
+        Commands::Benchmark { dir_path, backend, jobs } => {
             use benchmark::run_benchmark;
-            run_benchmark(Path::new(&dir_path))?;
+            run_benchmark(Path::new(&dir_path), backend, worker_count(jobs))?;
         }
     }
     Ok(())
 }
 
-fn main() -> io::Result<()> {   
-
+fn main() -> ExitCode {   
     match entry() {
         Ok(()) => ExitCode::SUCCESS, 
-        Err(_) => ExitCode::FAILURE 
-    };
-    Ok(())
+        Err(err) => {
+            eprintln!("{}: {err}", "ERROR".bold().red());
+            // Scripts can tell a bad query and an unusable index apart from any other failure
+            match err {
+                DocSenseError::Query { .. } => ExitCode::from(2),
+                DocSenseError::IndexNotFound { .. } | DocSenseError::IndexCorrupt { .. } | DocSenseError::IndexOutdated { .. } => ExitCode::from(3),
+                _ => ExitCode::FAILURE,
+            }
+        }
+    }
 }
 
 // TODO: Synonym terms / semantic expansion
@@ -1,17 +1,99 @@
 use std::{
-    path::{Path, PathBuf}, time::SystemTime
+    fs::File, io::{self, BufWriter, Write}, path::{Path, PathBuf}, time::{Duration, SystemTime, UNIX_EPOCH}
 };
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use clap::ValueEnum;
+use rusqlite::{params, Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
 use std::default::Default;
 
+use super::binary;
+use super::error::DocSenseError;
 use super::lexer::*;
+use super::parsers::{member_path, Fields, FormatSettings, ParsedDocument, TextKind};
+use super::query::*;
+use super::snippet::*;
 
 pub trait Model {
-    fn search_query(&self, query: &[char], model: &InMemoryModel, rank_method: RankMethod) -> Result<Vec<(PathBuf, f32)>, ()>;
-    fn add_document(&mut self, path: PathBuf, content: &[char], last_modified: SystemTime) -> Result<(), ()>;
-    fn requires_reindexing(&mut self, path: &Path, last_modified: SystemTime) -> Result<bool, ()>;
+    fn search_query(&self, query: &[char], rank_method: RankMethod) -> Result<Vec<(PathBuf, f32)>, DocSenseError>;
+    /* The `limit` best results of the query, each with snippets of the text around its matches */
+    fn search(&self, query: &[char], rank_method: RankMethod, limit: usize) -> Result<Vec<SearchResult>, DocSenseError>;
+    /* Add the documents parsed from a file, replacing everything indexed for it before */
+    fn add_documents(&mut self, path: PathBuf, documents: Vec<AnalyzedDocument>, last_modified: SystemTime) -> Result<(), DocSenseError>;
+    /* Remove every document of a file. Returns whether it had any. */
+    fn remove_documents(&mut self, path: &Path) -> Result<bool, DocSenseError>;
+    fn requires_reindexing(&mut self, path: &Path, last_modified: SystemTime) -> Result<bool, DocSenseError>;
+    /* Paths of the files with indexed documents */
+    fn indexed_paths(&self) -> Result<Vec<PathBuf>, DocSenseError>;
+    /* Parsed documents and modification time of an indexed file, e.g. to copy it into another index */
+    fn stored_documents(&self, path: &Path) -> Result<Option<(Vec<ParsedDocument>, SystemTime)>, DocSenseError>;
+    fn stats(&self) -> Result<Stats, DocSenseError>;
+    fn analysis(&self) -> Result<Analysis, DocSenseError>;
+    /* Only records the settings, documents analysed before keep the terms they were indexed with */
+    fn set_analysis(&mut self, analysis: Analysis) -> Result<(), DocSenseError>;
+    /* How the indexed files were parsed, see `ParseOptions::format_settings` */
+    fn format_settings(&self) -> Result<FormatSettings, DocSenseError>;
+    /* Only records the settings, like `set_analysis` */
+    fn set_format_settings(&mut self, settings: FormatSettings) -> Result<(), DocSenseError>;
+    /* Persist every change made so far to the index at `index_path` */
+    fn save(&mut self, index_path: &Path) -> Result<(), DocSenseError>;
+}
+
+/* Version of the stored index layout. Bumped whenever it changes, or the terms the lexer makes
+   of the same text do, indexes built by an older version are rebuilt from scratch instead of
+   being misread. */
+pub const INDEX_VERSION: u32 = 10;
+
+/* Number of snippets shown at most for every search result */
+const SNIPPETS_PER_RESULT: usize = 2;
+
+/* Fields which tell what a document is about better than its text does, with how much a word
+   found in them adds to the score of the same word found in the text */
+const WEIGHTED_FIELDS: [(&str, f32); 4] = [("title", 2.0), ("headings", 1.0), ("abstract", 0.5), ("captions", 0.5)];
+
+#[derive(Serialize)]
+pub struct SearchResult {
+    pub path: PathBuf,
+    pub locator: Option<String>,    // Part of the file that matched, see ParsedDocument
+    pub rank: f32,
+    pub fields: Fields,
+    pub snippets: Vec<Snippet>,
+}
+
+/* Settings of how documents are broken up into terms. They are stored with the index, as
+   documents analysed with other settings would not match the same queries. */
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Analysis {
+    pub stem_code: bool,    // Whether the words of source code are stemmed like those of prose
+}
+
+impl Default for Analysis {
+    fn default() -> Self {
+        Self { stem_code: true }
+    }
+}
+
+impl Analysis {
+    /* Lexer for the text of a document of the given kind */
+    pub fn lexer<'a>(&self, content: &'a [char], kind: TextKind) -> Lexer<'a> {
+        match kind {
+            TextKind::Prose => Lexer::new(content),
+            TextKind::Code => Lexer::new(content).code().stemmed(self.stem_code),
+        }
+    }
+}
+
+#[derive(Default, Serialize)]
+pub struct Stats {
+    pub doc_count: usize,
+    pub unique_term_count: usize,
+}
+
+#[derive(ValueEnum, Clone, Debug, PartialEq)]
+pub enum RankMethod {
+    Tfidf, 
+    Bm25
 }
 
 // ---- Associative types ----
@@ -23,35 +105,84 @@ pub type FreqTable = HashMap::<String, usize>;
    Map of term with frequency of occurence in all corpus of documents. */
 pub type GlobalTermFreq = HashMap::<String, usize>;
 
+/* Identifier of a document inside the inverted index. Assigned in increasing order,
+   so postings lists stay sorted by simply appending new documents. */
+pub type DocId = u32;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Posting {
+    pub doc: DocId,                 // Document containing the term
+    pub tf: usize,                  // Number of times the term occurs in that document
+    pub positions: Vec<u32>,        // Sorted token positions of the term inside that document
+}
+
+/* Answers which documents contain a term.
+   Map of term with its postings list, sorted by doc id. */
+pub type InvertedIndex = HashMap::<String, Vec<Posting>>;
+
 #[derive(Serialize, Deserialize)]
 pub struct Doc {
-    count: usize,                   // Total number of terms (tokens) present in this document.
-    ft: FreqTable,                  // Frequency table mapping each term to the number of times it appears within this document
-    last_modified: SystemTime       // The last time this document was modified on disk. Used to detect outdated indexes and trigger reindexing when needed.
+    pub path: PathBuf,              // File this document was parsed from
+    #[serde(default)]
+    pub locator: Option<String>,    // Where in that file the document is, see ParsedDocument
+    #[serde(default)]
+    pub kind: TextKind,             // How its text was broken up into terms
+    #[serde(default)]
+    pub member: Option<String>,     // Member of the archive at `path` it was parsed from, see ParsedDocument
+    pub count: usize,               // Total number of terms (tokens) present in this document.
+    pub ft: FreqTable,              // Frequency table mapping each term to the number of times it appears within this document
+    #[serde(default)]
+    pub text: String,               // Parsed content of the document, which search result snippets are cut from
+    #[serde(default)]
+    pub fields: Fields,             // Title, author and the like, as far as the parser found them
+    pub last_modified: SystemTime   // The last time this document was modified on disk. Used to detect outdated indexes and trigger reindexing when needed.
 }
 
-pub type Docs = HashMap::<PathBuf, Doc>;
+/* Every document by the id its postings refer to it with */
+pub type Docs = HashMap::<DocId, Doc>;
 
 #[derive(Default, Deserialize, Serialize)]
 pub struct InMemoryModel {
     pub gtf: GlobalTermFreq,
     pub docs: Docs,
-    // Cached sum of all doc.count values. Kept in sync by add_document /
-    // remove_document so that avgdl can be computed in O(1) at query time.
+    // Ids of the documents parsed from every indexed file, in the order the parser yielded them.
+    #[serde(default)]
+    pub files: HashMap::<PathBuf, Vec<DocId>>,
+    // Cached sum of all doc.count values. Kept in sync by add_documents /
+    // remove_documents so that avgdl can be computed in O(1) at query time.
     pub total_tokens: usize,
-}
-fn compute_avgdl(model: &InMemoryModel) -> f32 {
-    if model.docs.is_empty() { return 0.0; }
-    model.total_tokens as f32 / model.docs.len() as f32
+    // Term -> postings, so scoring only visits documents containing a query term
+    // and the document frequency of a term is just the length of its postings list.
+    #[serde(default)]
+    pub index: InvertedIndex,
+    #[serde(default)]
+    pub next_id: DocId,
+    #[serde(default)]
+    pub analysis: Analysis,
+    #[serde(default)]
+    pub formats: FormatSettings,
+    #[serde(default)]
+    pub version: u32,
+    // File format `save` writes, not part of the stored model itself
+    #[serde(skip)]
+    pub format: FileFormat,
 }
 
-fn compute_idf(term: &str, model: &InMemoryModel) -> f32 {
-    // Number of documents in corpus
-    let total_docs = model.docs.len() as f32;                           
-
-    // Number of documents containing the unique 'term'
-    let doc_freq = model.docs.values().filter(|doc| doc.ft.contains_key(term)).count() as f32;
+/* How an in-memory model is stored on disk */
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub enum FileFormat {
+    #[default]
+    Json,
+    Binary,     // See binary.rs
+}
+fn compute_avgdl(total_tokens: usize, total_docs: usize) -> f32 {
+    if total_docs == 0 { return 0.0; }
+    total_tokens as f32 / total_docs as f32
+}
 
+fn compute_idf(total_docs: usize, doc_freq: usize) -> f32 {
+    let total_docs = total_docs as f32;
+    let doc_freq = doc_freq as f32;
     f32::ln(((total_docs - doc_freq + 0.5) + 1f32) / (doc_freq + 0.5))
 }
 
@@ -76,29 +207,28 @@ fn levenshtein_distance(a: &str, b: &str) -> usize {
 }
 
 /// Expands a single stemmed/uppercased query token into a list of `(indexed_term, weight)`
-/// pairs drawn from the corpus's GTF, using:
+/// pairs drawn from the corpus vocabulary, using:
 ///   - Exact match            → weight 1.0
 ///   - Prefix overlap (≥4 ch) → weight ∝ overlap ratio × 0.85
-///   - Levenshtein distance   → weight ∝ similarity × 0.75
+///   - Levenshtein distance   → weight ∝ similarity × 0.75, for terms starting with the same char
 ///
 /// Tokens shorter than 4 chars only allow exact matches to avoid noisy expansion.
-fn expand_query_token(query_token: &str, gtf: &GlobalTermFreq) -> Vec<(String, f32)> {
+/// A token of a field (`title:TERM`) only expands into terms of that same field.
+fn expand_query_token(query_token: &str, vocabulary: &[&str]) -> Vec<(String, f32)> {
+    let (field, query_token) = split_field(query_token);
     let qlen = query_token.len();
-    let max_dist: usize = match qlen {
-        0..=3 => 0,
-        4..=5 => 1,
-        6..=7 => 1,
-        _ => 2,
-    };
+    let max_dist = max_edit_distance(qlen);
 
     let mut matches: HashMap<String, f32> = HashMap::new();
 
-    for term in gtf.keys() {
+    for &indexed in vocabulary {
+        let (term_field, term) = split_field(indexed);
+        if term_field != field { continue; }
         let tlen = term.len();
 
         // Exact match
-        if term.as_str() == query_token {
-            matches.insert(term.clone(), 1.0);
+        if term == query_token {
+            matches.insert(indexed.to_owned(), 1.0);
             continue;
         }
 
@@ -106,12 +236,12 @@ fn expand_query_token(query_token: &str, gtf: &GlobalTermFreq) -> Vec<(String, f
 
         // Prefix match: one token is a prefix of the other (min 4 chars)
         if qlen >= 4 && tlen >= 4 {
-            if term.starts_with(query_token) || query_token.starts_with(term.as_str()) {
+            if term.starts_with(query_token) || query_token.starts_with(term) {
                 let shorter = qlen.min(tlen) as f32;
                 let longer  = qlen.max(tlen) as f32;
                 let weight  = (shorter / longer) * 0.85;
                 if weight >= 0.5 {
-                    matches.entry(term.clone())
+                    matches.entry(indexed.to_owned())
                         .and_modify(|w| *w = w.max(weight))
                         .or_insert(weight);
                     continue;
@@ -119,13 +249,15 @@ fn expand_query_token(query_token: &str, gtf: &GlobalTermFreq) -> Vec<(String, f
             }
         }
 
-        // Levenshtein: skip pairs whose length difference already exceeds the budget
+        // Levenshtein: skip pairs whose length difference already exceeds the budget, and those
+        // which differ right away so a backend can look up candidates by their first char
         if qlen.abs_diff(tlen) > max_dist { continue; }
+        if term.chars().next() != query_token.chars().next() { continue; }
         let dist = levenshtein_distance(query_token, term);
         if dist > 0 && dist <= max_dist {
             let similarity = 1.0 - (dist as f32 / qlen.max(tlen) as f32);
             let weight = similarity * 0.75;
-            matches.entry(term.clone())
+            matches.entry(indexed.to_owned())
                 .and_modify(|w| *w = w.max(weight))
                 .or_insert(weight);
         }
@@ -134,137 +266,911 @@ fn expand_query_token(query_token: &str, gtf: &GlobalTermFreq) -> Vec<(String, f
     matches.into_iter().collect()
 }
 
+/// Edits a query token of `qlen` bytes may be away from a term it expands into.
+fn max_edit_distance(qlen: usize) -> usize {
+    match qlen {
+        0..=3 => 0,
+        4..=5 => 1,
+        6..=7 => 1,
+        _ => 2,
+    }
+}
+
 // K and B are free parameters, usually chosen, in absence of an advanced optimization, as K = [1.2, 2.0] and B = 0.75
 const K: f32 = 2.0;
 const B: f32 = 0.75;
-/// avgdl is pre-computed once per query. Each (term, weight) pair's contribution
-/// is scaled by `weight`, allowing fuzzy-matched tokens to contribute less than exact ones.
-fn bm25_score(query: &[(String, f32)], doc: &Doc, model: &InMemoryModel, avgdl: f32) -> f32 {
+/// Contribution of a single posting to the BM25 score of its document.
+/// avgdl and idf are pre-computed once per query term by the caller.
+fn bm25_score(tf: usize, doc_length: usize, idf: f32, avgdl: f32) -> f32 {
     // Ranking documents according to BM25 Algorithm: https://en.wikipedia.org/wiki/Okapi_BM25
     if avgdl == 0.0 { return 0.0; }  // guard: no tokens in corpus means undefined avgdl
-    let mut score = 0f32;
-    let doc_length = doc.count as f32;
-
-    for (term, weight) in query {
-        let tf = doc.ft.get(term.as_str()).copied().unwrap_or(0) as f32;
-        let idf = compute_idf(term, model);
-        let denom = tf + K * (1f32 - B + B * doc_length / avgdl);
-        if denom == 0.0 { continue; }
-        score += weight * idf * tf * (K + 1f32) / denom;
-    }
-    score
+    let tf = tf as f32;
+    let denom = tf + K * (1f32 - B + B * doc_length as f32 / avgdl);
+    if denom == 0.0 { return 0.0; }
+    idf * tf * (K + 1f32) / denom
 }
 
 // For TF-IDF Ranking
-fn tf(term: &str, doc: &Doc) -> f32 {
-    if doc.count == 0 { return 0.0; }  // guard: doc had no surviving tokens
-    let n = doc.ft.get(term).cloned().unwrap_or(0) as f32;  // occurrences in this doc
-    let d = doc.count as f32;                               // total tokens in this doc
-    n / d
+fn tf(occurrences: usize, doc_length: usize) -> f32 {
+    if doc_length == 0 { return 0.0; }  // guard: doc had no surviving tokens
+    occurrences as f32 / doc_length as f32
 }
 // For TF-IDF Ranking
-fn idf(term: &str, model: &InMemoryModel) -> f32 {
-    let n = model.docs.len() as f32;  // total docs in corpus
-    if n == 0.0 { return 0.0; }       // guard: empty corpus → no meaningful IDF
-    let d = model.gtf.get(term).cloned().unwrap_or(1) as f32;  // docs containing term
-    f32::log10(n / d)
+fn idf(total_docs: usize, doc_freq: usize) -> f32 {
+    if total_docs == 0 { return 0.0; }  // guard: empty corpus → no meaningful IDF
+    f32::log10(total_docs as f32 / doc_freq.max(1) as f32)
+}
+
+/* IDF of a term under the selected ranking method, computed once per query term */
+fn term_idf(rank_method: &RankMethod, total_docs: usize, doc_freq: usize) -> f32 {
+    match rank_method {
+        RankMethod::Bm25 => compute_idf(total_docs, doc_freq),
+        RankMethod::Tfidf => idf(total_docs, doc_freq),
+    }
+}
+
+/* Score contributed by a single posting. Shared by every Model backend so they rank identically. */
+fn posting_score(rank_method: &RankMethod, tf_count: usize, doc_length: usize, term_idf: f32, avgdl: f32) -> f32 {
+    match rank_method {
+        // BM-25 Ranking — avgdl pre-computed
+        RankMethod::Bm25 => bm25_score(tf_count, doc_length, term_idf, avgdl),
+        // TF-IDF Ranking
+        RankMethod::Tfidf => tf(tf_count, doc_length) * term_idf,
+    }
+}
+
+fn sort_by_rank<T>(results: &mut [(T, f32)]) {
+    // partial_cmp returns None only for NaN; treat NaN as equal rather than panicking.
+    results.sort_by(|(_, ra), (_, rb)| rb.partial_cmp(ra).unwrap_or(std::cmp::Ordering::Equal));
+}
+
+/* A parsed document broken up into its terms, ready to be added to a model. Analysing needs
+   no access to the model, so files can be tokenized in parallel and only merged under its lock. */
+pub struct AnalyzedDocument {
+    text: String,                               // Parsed content, kept for result snippets
+    fields: Fields,
+    locator: Option<String>,
+    kind: TextKind,
+    member: Option<String>,
+    positions: HashMap<String, Vec<u32>>,       // Every term with its positions in the document
+}
+
+impl AnalyzedDocument {
+    /* Count the occurrences and collect the positions of every term of a document.
+       Fields are always lexed as prose. */
+    pub fn new(document: ParsedDocument, analysis: &Analysis) -> Self {
+        let mut positions: HashMap<String, Vec<u32>> = HashMap::new();
+        let content = document.text.chars().collect::<Vec<_>>();
+        for (position, token) in analysis.lexer(&content, document.kind).positioned() {
+            positions.entry(token).or_default().push(position as u32);
+        }
+
+        // Field terms are told apart by their prefix, so their positions can simply start over
+        for (field, value) in &document.fields {
+            let value = value.chars().collect::<Vec<_>>();
+            for (position, token) in Lexer::new(&value).positioned() {
+                positions.entry(field_term(field, &token)).or_default().push(position as u32);
+            }
+        }
+        Self { text: document.text, fields: document.fields, locator: document.locator, kind: document.kind, member: document.member, positions }
+    }
+}
+
+// ---- Query evaluation ----
+/* Maps a query token to the indexed terms it expands to, with the weight of each */
+type Expansions = HashMap<String, Vec<(String, f32)>>;
+
+/* Documents matched by (a part of) the query, with their score */
+type Matches = HashMap<DocId, f32>;
+
+/* Matching documents sorted by their score, best first */
+type Ranking = Vec<(DocId, f32)>;
+
+/* A posting joined with the length of its document, as handed to the query evaluator */
+struct TermPosting {
+    doc: DocId,
+    tf: usize,
+    doc_length: usize,
+    positions: Vec<u32>,
+}
+
+/* Read access every backend provides, so queries are evaluated and ranked identically by all of them */
+trait IndexReader {
+    /* Number of documents and total number of tokens in the corpus */
+    fn corpus_size(&self) -> Result<(usize, usize), DocSenseError>;
+    /* Fuzzy expansions of every query token into indexed terms, see `expand_query_token` */
+    fn expand_terms(&self, tokens: &[&str]) -> Result<Expansions, DocSenseError>;
+    fn postings(&self, term: &str) -> Result<Vec<TermPosting>, DocSenseError>;
+    fn doc_path(&self, doc: DocId) -> Result<Option<PathBuf>, DocSenseError>;
+    fn document(&self, doc: DocId) -> Result<Option<ParsedDocument>, DocSenseError>;
+}
+
+/* Documents matching every term of the phrase at the right distance, with the score of those terms */
+fn score_phrase(index: &impl IndexReader, phrase: &Phrase, scoring: &Scoring) -> Result<Matches, DocSenseError> {
+    let mut term_postings = Vec::with_capacity(phrase.terms.len());
+    for (_, term) in &phrase.terms {
+        let postings = index.postings(term)?;
+        let term_idf = term_idf(scoring.rank_method, scoring.total_docs, postings.len());
+        let by_doc = postings.into_iter().map(|posting| (posting.doc, posting)).collect::<HashMap<_, _>>();
+        term_postings.push((term_idf, by_doc));
+    }
+
+    let mut scores = HashMap::new();
+    let Some((_, first)) = term_postings.first() else { return Ok(scores) };
+    'docs: for &doc in first.keys() {
+        let mut postings = Vec::with_capacity(term_postings.len());
+        for (_, by_doc) in &term_postings {
+            let Some(posting) = by_doc.get(&doc) else { continue 'docs };
+            postings.push(posting);
+        }
+
+        let positions = postings.iter().map(|posting| posting.positions.as_slice()).collect::<Vec<_>>();
+        if phrase_matches(phrase, &positions) == 0 {
+            continue 'docs;
+        }
+
+        let rank = postings.iter().zip(&term_postings)
+            .map(|(posting, (term_idf, _))| posting_score(scoring.rank_method, posting.tf, posting.doc_length, *term_idf, scoring.avgdl))
+            .sum();
+        scores.insert(doc, rank);
+    }
+    Ok(scores)
+}
+
+/* Corpus wide values every scored posting of a query needs */
+struct Scoring<'a> {
+    rank_method: &'a RankMethod,
+    total_docs: usize,
+    avgdl: f32,
+    expansions: Expansions,
+}
+
+/* Documents containing the term, or one of its fuzzy expansions unless an exact match is asked for */
+fn score_term(index: &impl IndexReader, term: &str, exact: bool, scoring: &Scoring) -> Result<Matches, DocSenseError> {
+    let exact_term = [(term.to_owned(), 1.0)];
+    let terms = match scoring.expansions.get(term) {
+        Some(expanded) if !exact => expanded.as_slice(),
+        _ => &exact_term,
+    };
+
+    let mut scores = Matches::new();
+    for (term, weight) in terms {
+        // A word not asked for in a field in particular scores more when it is in a weighted one too
+        let mut weighted = vec![(term.clone(), *weight)];
+        if split_field(term).0.is_none() {
+            weighted.extend(WEIGHTED_FIELDS.iter().map(|(field, boost)| (field_term(field, term), weight * boost)));
+        }
+
+        for (term, weight) in weighted {
+            let postings = index.postings(&term)?;
+            let term_idf = term_idf(scoring.rank_method, scoring.total_docs, postings.len());
+            for posting in postings {
+                // Weighted by fuzzy match quality
+                let rank = posting_score(scoring.rank_method, posting.tf, posting.doc_length, term_idf, scoring.avgdl);
+                *scores.entry(posting.doc).or_insert(0.0) += weight * rank;
+            }
+        }
+    }
+    Ok(scores)
+}
+
+/* Walk the query AST. Required clauses intersect, optional ones add to the score (and match on
+   their own when a group has nothing required), excluded ones reject documents. */
+fn evaluate_node(index: &impl IndexReader, node: &Node, exact: bool, scoring: &Scoring) -> Result<Matches, DocSenseError> {
+    match node {
+        Node::Term(term) => score_term(index, term, exact, scoring),
+        Node::Phrase(phrase) => score_phrase(index, phrase, scoring),
+        Node::Bool(clauses) => {
+            let mut required: Option<Matches> = None;
+            let mut optional = Matches::new();
+            let mut excluded = Vec::new();
+
+            for (occur, clause) in clauses {
+                match occur {
+                    Occur::Must => {
+                        let matches = evaluate_node(index, clause, exact, scoring)?;
+                        required = Some(match required {
+                            None => matches,
+                            Some(previous) => previous.into_iter()
+                                .filter_map(|(doc, rank)| matches.get(&doc).map(|extra| (doc, rank + extra)))
+                                .collect(),
+                        });
+                    }
+                    Occur::Should => {
+                        for (doc, rank) in evaluate_node(index, clause, exact, scoring)? {
+                            *optional.entry(doc).or_insert(0.0) += rank;
+                        }
+                    }
+                    // Only reject documents which really contain the excluded terms
+                    Occur::MustNot => excluded.push(evaluate_node(index, clause, true, scoring)?),
+                }
+            }
+
+            let mut matches = match required {
+                Some(required) => required.into_iter()
+                    .map(|(doc, rank)| (doc, rank + optional.get(&doc).copied().unwrap_or(0.0)))
+                    .collect(),
+                None => optional,
+            };
+            for rejected in excluded {
+                matches.retain(|doc, _| !rejected.contains_key(doc));
+            }
+            Ok(matches)
+        }
+    }
+}
+
+/* Indexed terms a document can match the query by, so they can be highlighted in snippets.
+   Excluded terms are left out, no document in the results contains them anyway. Snippets are
+   only cut from the text, so terms of a field are highlighted wherever the text holds them too. */
+fn highlight_terms(node: &Node, expansions: &Expansions, terms: &mut HashSet<String>) {
+    let bare = |term: &str| split_field(term).1.to_owned();
+    match node {
+        Node::Term(term) => match expansions.get(term) {
+            Some(expanded) => terms.extend(expanded.iter().map(|(term, _)| bare(term))),
+            None => { terms.insert(bare(term)); }
+        },
+        Node::Phrase(phrase) => terms.extend(phrase.terms.iter().map(|(_, term)| bare(term))),
+        Node::Bool(clauses) => {
+            for (occur, clause) in clauses {
+                if *occur != Occur::MustNot {
+                    highlight_terms(clause, expansions, terms);
+                }
+            }
+        }
+    }
+}
+
+/* Documents matching the query, best first, along with the terms to highlight in them */
+fn rank_query(index: &impl IndexReader, query: &[char], rank_method: &RankMethod, analysis: &Analysis) -> Result<(Ranking, HashSet<String>), DocSenseError> {
+    // Words of source code indexed without stemming only match as they are written
+    let Some(query) = parse_query(query, !analysis.stem_code)? else {
+        return Err(DocSenseError::Query { query: query.iter().collect(), reason: "has nothing to search for".to_owned() });
+    };
+
+    // Compute avgdl once per query
+    let (total_docs, total_tokens) = index.corpus_size()?;
+    let scoring = Scoring {
+        rank_method,
+        total_docs,
+        avgdl: compute_avgdl(total_tokens, total_docs),
+        expansions: index.expand_terms(&query.terms())?,
+    };
+
+    let mut ranked = evaluate_node(index, &query, false, &scoring)?.into_iter().collect::<Vec<_>>();
+    sort_by_rank(&mut ranked);
+
+    let mut terms = HashSet::new();
+    highlight_terms(&query, &scoring.expansions, &mut terms);
+    Ok((ranked, terms))
+}
+
+fn evaluate_query(index: &impl IndexReader, query: &[char], rank_method: &RankMethod, analysis: &Analysis) -> Result<Vec<(PathBuf, f32)>, DocSenseError> {
+    let (ranked, _) = rank_query(index, query, rank_method, analysis)?;
+    let mut results = Vec::with_capacity(ranked.len());
+    for (doc, rank) in ranked {
+        if let Some(path) = index.doc_path(doc)? {
+            results.push((path, rank));
+        }
+    }
+    Ok(results)
+}
+
+/* Same as `evaluate_query`, but only the best `limit` results which also get their snippets */
+fn evaluate_search(index: &impl IndexReader, query: &[char], rank_method: &RankMethod, limit: usize, analysis: &Analysis) -> Result<Vec<SearchResult>, DocSenseError> {
+    let (ranked, terms) = rank_query(index, query, rank_method, analysis)?;
+    let mut results = Vec::with_capacity(limit.min(ranked.len()));
+    for (doc, rank) in ranked.into_iter().take(limit) {
+        let Some(path) = index.doc_path(doc)? else { continue };
+        let ParsedDocument { text, fields, locator, kind, member, .. } = index.document(doc)?.unwrap_or_default();
+        // Members of an archive are listed by their virtual path, e.g. bundle.zip!/docs/intro.md
+        let path = match &member {
+            Some(member) => member_path(&path, member),
+            None => path,
+        };
+        let content = text.chars().collect::<Vec<_>>();
+        let snippets = make_snippets(&content, analysis.lexer(&content, kind), &terms, SNIPPETS_PER_RESULT);
+        results.push(SearchResult { path, locator, rank, fields, snippets });
+    }
+    Ok(results)
+}
+
+impl IndexReader for InMemoryModel {
+    fn corpus_size(&self) -> Result<(usize, usize), DocSenseError> {
+        Ok((self.docs.len(), self.total_tokens))
+    }
+
+    fn expand_terms(&self, tokens: &[&str]) -> Result<Expansions, DocSenseError> {
+        let vocabulary = self.gtf.keys().map(String::as_str).collect::<Vec<_>>();
+        Ok(tokens.iter().map(|&token| (token.to_owned(), expand_query_token(token, &vocabulary))).collect())
+    }
+
+    fn postings(&self, term: &str) -> Result<Vec<TermPosting>, DocSenseError> {
+        let Some(postings) = self.index.get(term) else { return Ok(Vec::new()) };
+        Ok(postings.iter().filter_map(|posting| {
+            let doc = self.docs.get(&posting.doc)?;
+            Some(TermPosting { doc: posting.doc, tf: posting.tf, doc_length: doc.count, positions: posting.positions.clone() })
+        }).collect())
+    }
+
+    fn doc_path(&self, doc: DocId) -> Result<Option<PathBuf>, DocSenseError> {
+        Ok(self.docs.get(&doc).map(|doc| doc.path.clone()))
+    }
+
+    fn document(&self, doc: DocId) -> Result<Option<ParsedDocument>, DocSenseError> {
+        Ok(self.docs.get(&doc).map(Doc::parsed))
+    }
+}
+
+impl Doc {
+    fn parsed(&self) -> ParsedDocument {
+        ParsedDocument { text: self.text.clone(), fields: self.fields.clone(), locator: self.locator.clone(), kind: self.kind, member: self.member.clone(), lossy: false }
+    }
 }
 
 impl InMemoryModel {
-    pub fn remove_document(&mut self, file_path: &Path) {
-        if let Some(doc) = self.docs.remove(file_path) {
-            // Keep the cached total in sync
-            self.total_tokens = self.total_tokens.saturating_sub(doc.count);
-            for term in doc.ft.keys() {
-                // Update the GlobalTermFrequency table
-                if let Some(freq) = self.gtf.get_mut(term) {
-                    *freq = freq.saturating_sub(1);
+    fn remove_doc(&mut self, id: DocId) {
+        let Some(doc) = self.docs.remove(&id) else { return };
+        // Keep the cached total in sync
+        self.total_tokens = self.total_tokens.saturating_sub(doc.count);
+        for term in doc.ft.keys() {
+            // Update the GlobalTermFrequency table
+            if let Some(freq) = self.gtf.get_mut(term) {
+                *freq = freq.saturating_sub(1);
+                if *freq == 0 {
+                    self.gtf.remove(term);
+                }
+            }
+
+            // Drop the document from the term's postings list
+            if let Some(postings) = self.index.get_mut(term) {
+                if let Ok(i) = postings.binary_search_by_key(&id, |p| p.doc) {
+                    postings.remove(i);
+                }
+                if postings.is_empty() {
+                    self.index.remove(term);
                 }
             }
         }
     }
 }
 
-use crate::RankMethod;
 impl Model for InMemoryModel {
-    fn search_query(&self, query: &[char], model: &InMemoryModel, rank_method: RankMethod) -> Result<Vec<(PathBuf, f32)>, ()> {
-        let tokens = Lexer::new(query).collect::<Vec<_>>();
-
-        // Expand each query token into (indexed_term, weight) pairs via exact,
-        // prefix, and Levenshtein fuzzy matching. If the same indexed term is
-        // reached through multiple paths, keep the highest weight.
-        let mut token_weights: HashMap<String, f32> = HashMap::new();
-        for token in &tokens {
-            for (matched_term, weight) in expand_query_token(token, &self.gtf) {
-                token_weights
-                    .entry(matched_term)
-                    .and_modify(|w| *w = w.max(weight))
-                    .or_insert(weight);
+    fn search_query(&self, query: &[char], rank_method: RankMethod) -> Result<Vec<(PathBuf, f32)>, DocSenseError> {
+        evaluate_query(self, query, &rank_method, &self.analysis()?)
+    }
+
+    fn search(&self, query: &[char], rank_method: RankMethod, limit: usize) -> Result<Vec<SearchResult>, DocSenseError> {
+        evaluate_search(self, query, &rank_method, limit, &self.analysis()?)
+    }
+
+    fn add_documents(&mut self, file_path: PathBuf, documents: Vec<AnalyzedDocument>, last_modified: SystemTime) -> Result<(), DocSenseError> {
+        // Remove earlier documents
+        self.remove_documents(&file_path)?;
+
+        let mut ids = Vec::with_capacity(documents.len());
+        for document in documents {
+            let AnalyzedDocument { text, fields, locator, kind, member, positions } = document;
+            let ft = positions.iter().map(|(term, positions)| (term.clone(), positions.len())).collect::<FreqTable>();
+
+            // Total count of terms in FreqTable
+            let term_count: usize = ft.values().sum();
+
+            // Skip documents with no surviving tokens (e.g. all content was stop words).
+            // Indexing them would give doc.count=0, causing tf()=0/0=NaN at query time.
+            if term_count == 0 {
+                continue;
             }
+
+            // Ids only ever grow, so appending keeps every postings list sorted
+            let id = self.next_id;
+            self.next_id += 1;
+
+            // Update global term frequency and the postings of every term
+            for (term, positions) in positions {
+                self.gtf.entry(term.to_owned()).and_modify(|x| *x += 1).or_insert(1);
+                self.index.entry(term).or_default().push(Posting { doc: id, tf: positions.len(), positions });
+            }
+
+            // Keep the cached total in sync
+            self.total_tokens += term_count;
+
+            // Update the Docs table
+            self.docs.insert(id, Doc { path: file_path.clone(), locator, kind, member, count: term_count, ft, text, fields, last_modified });
+            ids.push(id);
         }
-        let expanded: Vec<(String, f32)> = token_weights.into_iter().collect();
-
-        // Compute avgdl once per query (O(1) with cached total_tokens).
-        let avgdl = compute_avgdl(self);
-
-        let mut results = Vec::with_capacity(self.docs.len());
-        for (path, doc) in &self.docs {
-            let rank = if rank_method == RankMethod::Bm25 {
-                // BM-25 Ranking — weighted fuzzy tokens, avgdl pre-computed
-                bm25_score(&expanded, doc, model, avgdl)
-            } else {
-                // TF-IDF Ranking — weighted by fuzzy match quality
-                expanded.iter()
-                    .map(|(token, weight)| tf(token, doc) * idf(token, model) * weight)
-                    .sum()
-            };
-            results.push((path.to_owned(), rank));
+
+        if !ids.is_empty() {
+            self.files.insert(file_path, ids);
+        }
+        Ok(())
+    }
+
+    fn remove_documents(&mut self, file_path: &Path) -> Result<bool, DocSenseError> {
+        let Some(ids) = self.files.remove(file_path) else { return Ok(false) };
+        for id in ids {
+            self.remove_doc(id);
         }
+        Ok(true)
+    }
+
+    fn requires_reindexing(&mut self, file_path: &Path, last_modified: SystemTime) -> Result<bool, DocSenseError> {
+        let doc = self.files.get(file_path).and_then(|ids| ids.first()).and_then(|id| self.docs.get(id));
+        if let Some(doc) = doc {
+            return Ok(doc.last_modified < last_modified);
+        }
+        return Ok(true);
+    }
 
-        // partial_cmp returns None only for NaN; treat NaN as equal rather than panicking.
-        results.sort_by(|(_, ra), (_, rb)| rb.partial_cmp(ra).unwrap_or(std::cmp::Ordering::Equal));
-        Ok(results)
+    fn indexed_paths(&self) -> Result<Vec<PathBuf>, DocSenseError> {
+        Ok(self.files.keys().cloned().collect())
     }
 
-    fn add_document(&mut self, file_path: PathBuf, content: &[char], last_modified: SystemTime) -> Result<(), ()> {
-        // Remove earlier document
-        self.remove_document(&file_path);
+    fn stored_documents(&self, path: &Path) -> Result<Option<(Vec<ParsedDocument>, SystemTime)>, DocSenseError> {
+        let Some(ids) = self.files.get(path) else { return Ok(None) };
+        let docs = ids.iter().filter_map(|id| self.docs.get(id)).collect::<Vec<_>>();
+        let Some(first) = docs.first() else { return Ok(None) };
+        Ok(Some((docs.iter().map(|doc| doc.parsed()).collect(), first.last_modified)))
+    }
+
+    fn stats(&self) -> Result<Stats, DocSenseError> {
+        Ok(Stats { doc_count: self.docs.len(), unique_term_count: self.gtf.len() })
+    }
+
+    fn analysis(&self) -> Result<Analysis, DocSenseError> {
+        Ok(self.analysis)
+    }
+
+    fn set_analysis(&mut self, analysis: Analysis) -> Result<(), DocSenseError> {
+        self.analysis = analysis;
+        Ok(())
+    }
+
+    fn format_settings(&self) -> Result<FormatSettings, DocSenseError> {
+        Ok(self.formats.clone())
+    }
+
+    fn set_format_settings(&mut self, settings: FormatSettings) -> Result<(), DocSenseError> {
+        self.formats = settings;
+        Ok(())
+    }
+
+    /* Save the model to a path as json or binary file, depending on its format */
+    fn save(&mut self, index_path: &Path) -> Result<(), DocSenseError> {
+        let index_file = File::create(index_path).map_err(|err| DocSenseError::io(index_path, err))?;
 
-        // Precompute all the tokens at once 
-        let tokens = Lexer::new(&content).collect::<Vec<_>>();
-        let mut ft = FreqTable::new();
-        for token in &tokens {
-            ft.entry(token.clone()).and_modify(|x| *x += 1).or_insert(1);
+        self.version = INDEX_VERSION;
+        let saved = match self.format {
+            FileFormat::Json => serde_json::to_writer(BufWriter::new(index_file), &*self).map_err(io::Error::from),
+            FileFormat::Binary => BufWriter::new(index_file).write_all(&binary::encode(self)),
+        };
+        saved.map_err(|err| DocSenseError::io(index_path, err))
+    }
+}
+
+// ---- SQLite backed model ----
+/* Keeps docs, their term frequencies and the postings on disk in a single SQLite database,
+   so large indexes can be opened and queried without loading everything into memory. */
+pub struct SqliteModel {
+    conn: Connection,
+}
+
+const SQLITE_SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS docs (
+        id            INTEGER PRIMARY KEY,
+        path          TEXT NOT NULL,
+        locator       TEXT,
+        kind          INTEGER NOT NULL,
+        member        TEXT,
+        count         INTEGER NOT NULL,
+        last_modified INTEGER NOT NULL,
+        text          TEXT NOT NULL,
+        fields        TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS terms (
+        id   INTEGER PRIMARY KEY,
+        term TEXT NOT NULL UNIQUE,
+        df   INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS postings (
+        term_id INTEGER NOT NULL,
+        doc_id  INTEGER NOT NULL,
+        tf      INTEGER NOT NULL,
+        positions BLOB NOT NULL,
+        PRIMARY KEY (term_id, doc_id)
+    ) WITHOUT ROWID;
+    CREATE INDEX IF NOT EXISTS docs_by_path ON docs (path);
+    CREATE INDEX IF NOT EXISTS postings_by_doc ON postings (doc_id);
+    CREATE TABLE IF NOT EXISTS meta (
+        key   TEXT PRIMARY KEY,
+        value INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS formats (
+        ext      TEXT PRIMARY KEY,
+        settings TEXT NOT NULL
+    );
+    INSERT OR IGNORE INTO meta (key, value) VALUES ('total_tokens', 0);
+    INSERT OR IGNORE INTO meta (key, value) VALUES ('stem_code', 1);
+";
+
+/* SQLite has no native time type, so modification times are stored as nanoseconds since the epoch */
+fn system_time_to_nanos(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as i64).unwrap_or(0)
+}
+
+fn nanos_to_system_time(nanos: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_nanos(nanos.max(0) as u64)
+}
+
+/* Positions are stored as a blob of little endian u32s */
+fn encode_positions(positions: &[u32]) -> Vec<u8> {
+    positions.iter().flat_map(|position| position.to_le_bytes()).collect()
+}
+
+fn decode_positions(blob: &[u8]) -> Vec<u32> {
+    blob.chunks_exact(4).map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])).collect()
+}
+
+/* Kinds of text are stored by their number, also used by the binary format */
+pub fn encode_kind(kind: TextKind) -> u8 {
+    match kind {
+        TextKind::Prose => 0,
+        TextKind::Code => 1,
+    }
+}
+
+pub fn decode_kind(kind: u8) -> Option<TextKind> {
+    match kind {
+        0 => Some(TextKind::Prose),
+        1 => Some(TextKind::Code),
+        _ => None,
+    }
+}
+
+/* Fields are stored as a JSON object */
+fn encode_fields(fields: &Fields) -> String {
+    serde_json::to_string(fields).unwrap_or_default()
+}
+
+fn decode_fields(json: &str) -> Fields {
+    serde_json::from_str(json).unwrap_or_default()
+}
+
+/* The text, fields, locator, kind and member columns of a row of the docs table */
+fn stored_parsed_document(row: &rusqlite::Row) -> rusqlite::Result<ParsedDocument> {
+    Ok(ParsedDocument {
+        text: row.get(0)?,
+        fields: decode_fields(&row.get::<_, String>(1)?),
+        locator: row.get(2)?,
+        kind: decode_kind(row.get(3)?).unwrap_or_default(),
+        member: row.get(4)?,
+        lossy: false,
+    })
+}
+
+impl SqliteModel {
+    /* Open the database at `index_path`, creating the file and the schema when missing */
+    pub fn open(index_path: &Path) -> Result<Self, DocSenseError> {
+        let conn = Connection::open(index_path)?;
+        let fresh = !conn.query_row("SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE name = 'docs')", [], |row| row.get::<_, bool>(0))?;
+        let model = Self { conn };
+        if fresh {
+            model.create_schema()?;
         }
-        
-        // Total count of terms in FreqTable
-        let term_count: usize = ft.values().sum();
+        Ok(model)
+    }
+
+    fn create_schema(&self) -> Result<(), DocSenseError> {
+        self.conn.execute_batch(SQLITE_SCHEMA)?;
+        self.conn.pragma_update(None, "user_version", INDEX_VERSION).map_err(DocSenseError::from)
+    }
+
+    /* Layout version the database was created with, see INDEX_VERSION */
+    pub fn version(&self) -> Result<u32, DocSenseError> {
+        self.conn.query_row("PRAGMA user_version", [], |row| row.get::<_, u32>(0)).map_err(DocSenseError::from)
+    }
 
-        // Skip documents with no surviving tokens (e.g. all content was stop words).
-        // Indexing them would give doc.count=0, causing tf()=0/0=NaN at query time.
-        if term_count == 0 {
-            return Ok(());
+    /* Drop everything stored so far and start over with an empty index */
+    pub fn reset(&mut self) -> Result<(), DocSenseError> {
+        self.conn.execute_batch("
+            DROP TABLE IF EXISTS postings;
+            DROP TABLE IF EXISTS terms;
+            DROP TABLE IF EXISTS docs;
+            DROP TABLE IF EXISTS meta;
+            DROP TABLE IF EXISTS formats;
+        ")?;
+        self.create_schema()
+    }
+
+    /* The terms a query token may expand into (see `expand_query_token`), looked up through the
+       index of the terms table rather than by reading the whole vocabulary: the term itself, the
+       terms it is a prefix of, the prefixes of it and the terms of about its length starting
+       with the same char. */
+    fn expansion_candidates(&self, token: &str) -> Result<Vec<String>, DocSenseError> {
+        let (field, bare) = split_field(token);
+        let max_dist = max_edit_distance(bare.len());
+
+        let mut candidates = HashSet::new();
+        let mut exact = self.conn.prepare_cached("SELECT term FROM terms WHERE term = ?1")?;
+        candidates.extend(exact.query_row(params![token], |row| row.get::<_, String>(0)).optional()?);
+        if max_dist == 0 {
+            return Ok(candidates.into_iter().collect());
         }
 
-        // Update global term frequency
-        for term in ft.keys() {
-            self.gtf.entry(term.to_owned()).and_modify(|x| *x += 1).or_insert(1);
+        // Terms starting with the token sort right after it
+        let mut longer = self.conn.prepare_cached("SELECT term FROM terms WHERE term > ?1 AND term < ?2")?;
+        let terms = longer.query_map(params![token, format!("{token}{}", char::MAX)], |row| row.get::<_, String>(0))?;
+        candidates.extend(terms.collect::<Result<Vec<_>, _>>()?);
+
+        // Prefixes of the token down to the 4 chars a prefix match needs
+        let prefix_start = token.len() - bare.len();
+        for (end, _) in bare.char_indices().skip(4) {
+            candidates.extend(exact.query_row(params![&token[..prefix_start + end]], |row| row.get::<_, String>(0)).optional()?);
+        }
+
+        // Terms within edit distance start with the same char and sort between it and the next one,
+        // those of another field or of none (upper case) outside of that. They are also at most
+        // that many bytes longer or shorter.
+        let Some(first) = bare.chars().next() else { return Ok(candidates.into_iter().collect()) };
+        let field_prefix = field.map(|field| format!("{field}:")).unwrap_or_default();
+        let next = char::from_u32(first as u32 + 1).unwrap_or(char::MAX);
+        let (shortest, longest) = (token.len().saturating_sub(max_dist) as i64, (token.len() + max_dist) as i64);
+        let mut similar = self.conn.prepare_cached("
+            SELECT term FROM terms
+            WHERE term >= ?1 AND term < ?2 AND octet_length(term) BETWEEN ?3 AND ?4
+        ")?;
+        let terms = similar.query_map(params![format!("{field_prefix}{first}"), format!("{field_prefix}{next}"), shortest, longest], |row| row.get::<_, String>(0))?;
+        candidates.extend(terms.collect::<Result<Vec<_>, _>>()?);
+        Ok(candidates.into_iter().collect())
+    }
+
+    /* Writes are batched into one transaction which is committed by `save` */
+    fn begin(&self) -> Result<(), DocSenseError> {
+        if self.conn.is_autocommit() {
+            self.conn.execute_batch("BEGIN")?;
+        }
+        Ok(())
+    }
+
+    fn doc_count(&self) -> Result<usize, DocSenseError> {
+        self.conn.query_row("SELECT COUNT(*) FROM docs", [], |row| row.get::<_, i64>(0))
+            .map(|n| n as usize)
+            .map_err(DocSenseError::from)
+    }
+
+    fn total_tokens(&self) -> Result<usize, DocSenseError> {
+        self.conn.query_row("SELECT value FROM meta WHERE key = 'total_tokens'", [], |row| row.get::<_, i64>(0))
+            .map(|n| n as usize)
+            .map_err(DocSenseError::from)
+    }
+}
+
+impl IndexReader for SqliteModel {
+    fn corpus_size(&self) -> Result<(usize, usize), DocSenseError> {
+        Ok((self.doc_count()?, self.total_tokens()?))
+    }
+
+    fn expand_terms(&self, tokens: &[&str]) -> Result<Expansions, DocSenseError> {
+        if tokens.is_empty() {
+            return Ok(Expansions::new());
+        }
+        let mut expansions = Expansions::new();
+        for &token in tokens {
+            let candidates = self.expansion_candidates(token)?;
+            let vocabulary = candidates.iter().map(String::as_str).collect::<Vec<_>>();
+            expansions.insert(token.to_owned(), expand_query_token(token, &vocabulary));
+        }
+        Ok(expansions)
+    }
+
+    fn postings(&self, term: &str) -> Result<Vec<TermPosting>, DocSenseError> {
+        let mut stmt = self.conn.prepare_cached("
+            SELECT postings.doc_id, postings.tf, docs.count, postings.positions
+            FROM terms
+            JOIN postings ON postings.term_id = terms.id
+            JOIN docs ON docs.id = postings.doc_id
+            WHERE terms.term = ?1
+        ")?;
+        let postings = stmt.query_map(params![term], |row| {
+            Ok(TermPosting {
+                doc: row.get::<_, i64>(0)? as DocId,
+                tf: row.get::<_, i64>(1)? as usize,
+                doc_length: row.get::<_, i64>(2)? as usize,
+                positions: decode_positions(&row.get::<_, Vec<u8>>(3)?),
+            })
+        })?;
+        postings.collect::<Result<Vec<_>, _>>().map_err(DocSenseError::from)
+    }
+
+    fn doc_path(&self, doc: DocId) -> Result<Option<PathBuf>, DocSenseError> {
+        let mut stmt = self.conn.prepare_cached("SELECT path FROM docs WHERE id = ?1")?;
+        stmt.query_row(params![doc as i64], |row| row.get::<_, String>(0))
+            .optional()
+            .map(|path| path.map(PathBuf::from))
+            .map_err(DocSenseError::from)
+    }
+
+    fn document(&self, doc: DocId) -> Result<Option<ParsedDocument>, DocSenseError> {
+        let mut stmt = self.conn.prepare_cached("SELECT text, fields, locator, kind, member FROM docs WHERE id = ?1")?;
+        stmt.query_row(params![doc as i64], stored_parsed_document)
+            .optional()
+            .map_err(DocSenseError::from)
+    }
+}
+
+impl Model for SqliteModel {
+    fn search_query(&self, query: &[char], rank_method: RankMethod) -> Result<Vec<(PathBuf, f32)>, DocSenseError> {
+        evaluate_query(self, query, &rank_method, &self.analysis()?)
+    }
+
+    fn search(&self, query: &[char], rank_method: RankMethod, limit: usize) -> Result<Vec<SearchResult>, DocSenseError> {
+        evaluate_search(self, query, &rank_method, limit, &self.analysis()?)
+    }
+
+    fn add_documents(&mut self, file_path: PathBuf, documents: Vec<AnalyzedDocument>, last_modified: SystemTime) -> Result<(), DocSenseError> {
+        // Remove earlier documents
+        self.remove_documents(&file_path)?;
+
+        self.begin()?;
+        let path = file_path.to_string_lossy();
+        let mut doc_stmt = self.conn.prepare_cached("INSERT INTO docs (path, locator, kind, member, count, last_modified, text, fields) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)")?;
+        let mut term_stmt = self.conn.prepare_cached("
+            INSERT INTO terms (term, df) VALUES (?1, 1)
+            ON CONFLICT (term) DO UPDATE SET df = df + 1
+            RETURNING id
+        ")?;
+        let mut posting_stmt = self.conn.prepare_cached("INSERT INTO postings (term_id, doc_id, tf, positions) VALUES (?1, ?2, ?3, ?4)")?;
+
+        let mut total_count = 0;
+        for document in documents {
+            let AnalyzedDocument { text, fields, locator, kind, member, positions } = document;
+
+            // Skip documents with no surviving tokens, same as the in-memory model
+            let term_count: usize = positions.values().map(Vec::len).sum();
+            if term_count == 0 {
+                continue;
+            }
+
+            doc_stmt.execute(params![path, locator, encode_kind(kind), member, term_count as i64, system_time_to_nanos(last_modified), text, encode_fields(&fields)])?;
+            let doc_id = self.conn.last_insert_rowid();
+
+            for (term, positions) in &positions {
+                let term_id = term_stmt.query_row(params![term], |row| row.get::<_, i64>(0))?;
+                posting_stmt.execute(params![term_id, doc_id, positions.len() as i64, encode_positions(positions)])?;
+            }
+            total_count += term_count;
         }
 
         // Keep the cached total in sync
-        self.total_tokens += term_count;
+        self.conn.execute("UPDATE meta SET value = value + ?1 WHERE key = 'total_tokens'", params![total_count as i64])?;
+        Ok(())
+    }
 
-        // Update the Docs table
-        self.docs.insert(file_path, Doc { count: term_count, ft, last_modified });
+    fn remove_documents(&mut self, file_path: &Path) -> Result<bool, DocSenseError> {
+        let path = file_path.to_string_lossy();
+        let mut stmt = self.conn.prepare_cached("SELECT id, count FROM docs WHERE path = ?1")?;
+        let docs = stmt.query_map(params![path], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)))?;
+        let docs = docs.collect::<Result<Vec<_>, _>>()?;
+        if docs.is_empty() {
+            return Ok(false);
+        }
+
+        self.begin()?;
+        for (doc_id, count) in docs {
+            self.conn.execute_batch(&format!("
+                UPDATE terms SET df = df - 1 WHERE id IN (SELECT term_id FROM postings WHERE doc_id = {doc_id});
+                DELETE FROM terms WHERE df <= 0 AND id IN (SELECT term_id FROM postings WHERE doc_id = {doc_id});
+                DELETE FROM postings WHERE doc_id = {doc_id};
+                DELETE FROM docs WHERE id = {doc_id};
+                UPDATE meta SET value = MAX(value - {count}, 0) WHERE key = 'total_tokens';
+            "))?;
+        }
+        Ok(true)
+    }
+
+    fn requires_reindexing(&mut self, file_path: &Path, last_modified: SystemTime) -> Result<bool, DocSenseError> {
+        let path = file_path.to_string_lossy();
+        let stored = self.conn
+            .query_row("SELECT MAX(last_modified) FROM docs WHERE path = ?1", params![path], |row| row.get::<_, Option<i64>>(0))
+            ?;
+        match stored {
+            Some(nanos) => Ok(nanos_to_system_time(nanos) < last_modified),
+            None => Ok(true),
+        }
+    }
+
+    fn indexed_paths(&self) -> Result<Vec<PathBuf>, DocSenseError> {
+        let mut stmt = self.conn.prepare_cached("SELECT DISTINCT path FROM docs")?;
+        let paths = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        paths.map(|path| path.map(PathBuf::from)).collect::<Result<Vec<_>, _>>().map_err(DocSenseError::from)
+    }
+
+    fn stored_documents(&self, path: &Path) -> Result<Option<(Vec<ParsedDocument>, SystemTime)>, DocSenseError> {
+        let mut stmt = self.conn.prepare_cached("SELECT text, fields, locator, kind, member, last_modified FROM docs WHERE path = ?1 ORDER BY id")?;
+        let rows = stmt.query_map(params![path.to_string_lossy()], |row| {
+            Ok((stored_parsed_document(row)?, nanos_to_system_time(row.get::<_, i64>(5)?)))
+        })?;
+        let rows = rows.collect::<Result<Vec<_>, _>>()?;
+        let Some(&(_, last_modified)) = rows.first() else { return Ok(None) };
+        Ok(Some((rows.into_iter().map(|(document, _)| document).collect(), last_modified)))
+    }
+
+    fn stats(&self) -> Result<Stats, DocSenseError> {
+        let unique_term_count = self.conn.query_row("SELECT COUNT(*) FROM terms", [], |row| row.get::<_, i64>(0))?;
+        Ok(Stats { doc_count: self.doc_count()?, unique_term_count: unique_term_count as usize })
+    }
+
+    fn analysis(&self) -> Result<Analysis, DocSenseError> {
+        let stem_code = self.conn.query_row("SELECT value FROM meta WHERE key = 'stem_code'", [], |row| row.get::<_, bool>(0))?;
+        Ok(Analysis { stem_code })
+    }
+
+    fn set_analysis(&mut self, analysis: Analysis) -> Result<(), DocSenseError> {
+        self.begin()?;
+        self.conn.execute("UPDATE meta SET value = ?1 WHERE key = 'stem_code'", params![analysis.stem_code])?;
         Ok(())
     }
 
-    fn requires_reindexing(&mut self, file_path: &Path, last_modified: SystemTime) -> Result<bool, ()> {
-        if let Some(doc) = self.docs.get(file_path) {
-            return Ok(doc.last_modified < last_modified);
+    fn format_settings(&self) -> Result<FormatSettings, DocSenseError> {
+        let mut stmt = self.conn.prepare_cached("SELECT ext, settings FROM formats")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?;
+        rows.collect::<Result<FormatSettings, _>>().map_err(DocSenseError::from)
+    }
+
+    fn set_format_settings(&mut self, settings: FormatSettings) -> Result<(), DocSenseError> {
+        self.begin()?;
+        self.conn.execute("DELETE FROM formats", [])?;
+        let mut insert = self.conn.prepare_cached("INSERT INTO formats (ext, settings) VALUES (?1, ?2)")?;
+        for (ext, parsed_as) in &settings {
+            insert.execute(params![ext, parsed_as])?;
         }
-        return Ok(true);
+        Ok(())
+    }
+
+    /* Changes already live in the database, saving only commits the pending transaction */
+    fn save(&mut self, _index_path: &Path) -> Result<(), DocSenseError> {
+        if !self.conn.is_autocommit() {
+            self.conn.execute_batch("COMMIT")?;
+        }
+        Ok(())
     }
 }
 
-// TODO: Implement an efficient sqlite Model with parallel processing support
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* Two documents of the same length holding the searched word once, in the title of the first */
+    fn title_and_body(model: &mut impl Model) {
+        let analysis = Analysis::default();
+        let documents = [
+            ("title.txt", "Transformers\nattention is all you need", Some("Transformers")),
+            ("body.txt", "Attention\nall you need is transformers", None),
+        ];
+        for (path, text, title) in documents {
+            let fields = title.map(|title| Fields::from([("title".to_owned(), title.to_owned())])).unwrap_or_default();
+            let document = ParsedDocument { text: text.to_owned(), fields, ..Default::default() };
+            model.add_documents(PathBuf::from(path), vec![AnalyzedDocument::new(document, &analysis)], SystemTime::now()).unwrap();
+        }
+    }
+
+    fn assert_title_first(model: &impl Model) {
+        for rank_method in [RankMethod::Bm25, RankMethod::Tfidf] {
+            let results = model.search_query(&"transformers".chars().collect::<Vec<_>>(), rank_method).unwrap();
+            let paths = results.iter().map(|(path, _)| path.to_str().unwrap()).collect::<Vec<_>>();
+            assert_eq!(paths, ["title.txt", "body.txt"]);
+            assert!(results[0].1 > results[1].1);
+        }
+    }
+
+    #[test]
+    fn title_match_outranks_body_match() {
+        let mut model = InMemoryModel::default();
+        title_and_body(&mut model);
+        assert_title_first(&model);
+
+        let mut model = SqliteModel::open(Path::new(":memory:")).unwrap();
+        title_and_body(&mut model);
+        assert_title_first(&model);
+    }
+}
@@ -1,5 +1,5 @@
 use clap::{Subcommand, command, Parser}; 
-use crate::RankMethod;
+use docsense::{Backend, RankMethod};
 
 #[derive(Parser)]
 #[command(name = "DocSense", version, author, about, long_about = None)]
@@ -16,7 +16,7 @@ pub enum Commands {
         long_about = "Search for a prompt string using BM25 (or TF-IDF) ranking algorithm across previously indexed documents."
     )]
     Search {
-        #[arg(help = "Path to the .json index file (e.g., index.docsense.json)")]
+        #[arg(help = "Path to the index file (e.g., .docsense.json, .docsense.bin or .docsense.sqlite3), its format is detected automatically")]
         index_file_path: String, 
         #[arg(help = "Search prompt string (e.g., 'deep neural networks')")]
         prompt: String, 
@@ -29,19 +29,40 @@ pub enum Commands {
         long_about = "Display number of documents currently indexed in the specified index file. Useful for verifying the index state."
     )]
     Check {
-        #[arg(default_value="index.json", help="Path to index file to inspect")]
+        #[arg(default_value="index.json", help="Path to index file to inspect, its format is detected automatically")]
         index_file_path: String, 
     }, 
 
+    #[command(
+        about = "Convert an index to another storage format",
+        long_about = "Copies every document of an index (json, binary or sqlite, detected automatically) into a new index stored in the selected format."
+    )]
+    Convert {
+        #[arg(help = "Path to the index to convert")]
+        input_path: String,
+        #[arg(help = "Path to write the converted index to, must not exist yet")]
+        output_path: String,
+        #[arg(short, long, value_enum, help = "Storage format of the converted index")]
+        to: Backend,
+    },
+
     #[command(
         about = "Index a directory for offline search",
-        long_about = "Indexes the provided directory recursively and saves the model to a JSON file."
+        long_about = "Indexes the provided directory recursively and saves the model to a JSON file or SQLite database."
     )]
     Index {
         #[arg(help = "Path to directory to index")]
         dir_path: String,
-        #[arg(help = "Path to save the generated index json file. Defaults to <dir_path>/.docsense.json")]
+        #[arg(help = "Path to save the generated index file. Defaults to <dir_path>/.docsense.json (or .docsense.sqlite3)")]
         output_file: Option<String>,
+        #[arg(short, long, value_enum, help = "Storage backend of the index. Defaults to json for a new index, an existing one has to be converted to change it")]
+        backend: Option<Backend>,
+        #[arg(short, long, help = "Number of files parsed in parallel. Defaults to the number of CPUs")]
+        jobs: Option<usize>,
+        #[arg(long, help = "Whether the words of source code are stemmed like prose (true or false). A new index stems them, an existing one keeps its setting unless given")]
+        stem_code: Option<bool>,
+        #[arg(long, help = "Path to a config file, which may set up external converters for other formats. Defaults to <dir_path>/.docsense.toml without its converters, those of subdirectories are not read")]
+        config: Option<String>,
     },
 
     #[command(
@@ -55,6 +76,14 @@ pub enum Commands {
         address: String, 
         #[arg(short, long, default_value = "tfidf", value_enum, help = "Ranking algorithm to use")]
         rank_method: RankMethod,
+        #[arg(short, long, value_enum, help = "Storage backend of the index. Defaults to json for a new index, an existing one has to be converted to change it")]
+        backend: Option<Backend>,
+        #[arg(short, long, help = "Number of files parsed in parallel. Defaults to the number of CPUs")]
+        jobs: Option<usize>,
+        #[arg(long, help = "Whether the words of source code are stemmed like prose (true or false). A new index stems them, an existing one keeps its setting unless given")]
+        stem_code: Option<bool>,
+        #[arg(long, help = "Path to a config file, which may set up external converters for other formats. Defaults to <dir_path>/.docsense.toml without its converters, those of subdirectories are not read")]
+        config: Option<String>,
     },
 
     #[command(
@@ -64,5 +93,9 @@ pub enum Commands {
     Benchmark {
         #[arg(help = "Path to the corpus directory to benchmark")]
         dir_path: String,
+        #[arg(short, long, default_value = "json", value_enum, help = "Storage backend of the index")]
+        backend: Backend,
+        #[arg(short, long, help = "Number of files parsed in parallel. Defaults to the number of CPUs")]
+        jobs: Option<usize>,
     }
 }
\ No newline at end of file
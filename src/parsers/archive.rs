@@ -0,0 +1,150 @@
+use std::{ffi::OsString, io::{Cursor, Read, Seek}, path::{Component, Path, PathBuf}};
+
+use tar::Archive;
+use zip::ZipArchive;
+
+use crate::error::DocSenseError;
+use super::compression::{decompress, inner_extension, open_decompressed, read_decompressed};
+use super::sniff::resolve_format;
+use super::{is_supported, parse_source, ParseOptions, ParsedDocument, ParsedFile};
+
+/* Extensions of the archives whose members are indexed one by one, e.g. bundle.zip or bundle.tar.gz */
+pub const ARCHIVE_EXTENSIONS: [&str; 2] = ["zip", "tar"];
+
+/* Separates the path of an archive from the path of a member in it, e.g. bundle.zip!/docs/intro.md */
+const MEMBER_SEPARATOR: &str = "!/";
+
+/* Virtual path of a member of an archive, which search results list it by */
+pub fn member_path(archive_path: &Path, member: &str) -> PathBuf {
+    let mut path = OsString::from(archive_path);
+    path.push(MEMBER_SEPARATOR);
+    path.push(member);
+    PathBuf::from(path)
+}
+
+/* The archive and the member a virtual path points at, None for the path of a plain file */
+pub fn split_member_path(path: &Path) -> Option<(PathBuf, String)> {
+    let path = path.to_str()?;
+    path.match_indices(MEMBER_SEPARATOR)
+        .map(|(at, _)| (Path::new(&path[..at]), &path[at + MEMBER_SEPARATOR.len()..]))
+        .find(|(archive, _)| is_archive(archive))
+        .map(|(archive, member)| (archive.to_path_buf(), member.to_owned()))
+}
+
+pub fn is_archive(file_path: &Path) -> bool {
+    inner_extension(file_path).is_some_and(|ext| ARCHIVE_EXTENSIONS.contains(&ext))
+}
+
+/* Parse every supported member of a zip or tar archive, `format` being which of the two it is. A tar
+   archive may be compressed as a whole, e.g. bundle.tar.gz. Members are parsed by their own extension
+   (and decompressed first if they are compressed themselves), their documents are told apart by the
+   path of the member. Archives inside the archive are not descended into. A member which can not be
+   parsed is left out and reported along with the documents, instead of failing the whole archive. */
+pub fn parse_archive_file(file_path: &Path, format: &str, options: &ParseOptions) -> Result<ParsedFile, DocSenseError> {
+    match format {
+        "zip" => parse_zip(file_path, Cursor::new(read_decompressed(file_path)?), options),
+        _ => parse_tar(file_path, open_decompressed(file_path)?, options),
+    }
+}
+
+/* Same as `parse_archive_file` for the contents of the archive already read */
+pub fn parse_archive_source(file_path: &Path, format: &str, source: &[u8], options: &ParseOptions) -> Result<ParsedFile, DocSenseError> {
+    match format {
+        "zip" => parse_zip(file_path, Cursor::new(source), options),
+        _ => parse_tar(file_path, source, options),
+    }
+}
+
+fn parse_zip(file_path: &Path, source: impl Read + Seek, options: &ParseOptions) -> Result<ParsedFile, DocSenseError> {
+    let mut parsed = ParsedFile::default();
+    let mut archive = ZipArchive::new(source).map_err(|err| DocSenseError::parse(file_path, "zip", err))?;
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(|err| DocSenseError::parse(file_path, "zip", err))?;
+        let member = entry.name().to_owned();
+        if !entry.is_file() || !is_indexed_member(&member, options) {
+            continue;
+        }
+        let mut source = Vec::new();
+        entry.read_to_end(&mut source).map_err(|err| DocSenseError::parse(file_path, "zip", format!("{member}: {err}")))?;
+        add_member(&mut parsed, file_path, &member, &source, options);
+    }
+    Ok(parsed)
+}
+
+fn parse_tar(file_path: &Path, source: impl Read, options: &ParseOptions) -> Result<ParsedFile, DocSenseError> {
+    let mut parsed = ParsedFile::default();
+    let mut archive = Archive::new(source);
+    for entry in archive.entries().map_err(|err| DocSenseError::parse(file_path, "tar", err))? {
+        let mut entry = entry.map_err(|err| DocSenseError::parse(file_path, "tar", err))?;
+        let Some(member) = entry.path().ok().and_then(|path| path.to_str().map(str::to_owned)) else { continue };
+        if !entry.header().entry_type().is_file() || !is_indexed_member(&member, options) {
+            continue;
+        }
+        let mut source = Vec::new();
+        entry.read_to_end(&mut source).map_err(|err| DocSenseError::parse(file_path, "tar", format!("{member}: {err}")))?;
+        add_member(&mut parsed, file_path, &member, &source, options);
+    }
+    Ok(parsed)
+}
+
+fn add_member(parsed: &mut ParsedFile, file_path: &Path, member: &str, source: &[u8], options: &ParseOptions) {
+    match parse_member(file_path, member, source, options) {
+        Ok(documents) => parsed.documents.extend(documents),
+        Err(err) => parsed.skipped.push(err),
+    }
+}
+
+/* Contents of a member of an archive, decompressed if the member is compressed itself */
+pub fn read_member(file_path: &Path, member: &str) -> Result<Vec<u8>, DocSenseError> {
+    let format = inner_extension(file_path).unwrap_or_default();
+    let not_found = || DocSenseError::parse(file_path, format, format!("it has no member {member}"));
+    let mut source = Vec::new();
+
+    match format {
+        "zip" => {
+            let mut archive = ZipArchive::new(Cursor::new(read_decompressed(file_path)?)).map_err(|err| DocSenseError::parse(file_path, format, err))?;
+            let mut entry = archive.by_name(member).map_err(|_| not_found())?;
+            entry.read_to_end(&mut source).map_err(|err| DocSenseError::parse(file_path, format, format!("{member}: {err}")))?;
+        }
+        "tar" => {
+            let mut archive = Archive::new(open_decompressed(file_path)?);
+            let mut entries = archive.entries().map_err(|err| DocSenseError::parse(file_path, format, err))?;
+            let mut entry = entries
+                .find(|entry| entry.as_ref().is_ok_and(|entry| entry.path().is_ok_and(|path| path.to_str() == Some(member))))
+                .ok_or_else(not_found)?
+                .map_err(|err| DocSenseError::parse(file_path, format, err))?;
+            entry.read_to_end(&mut source).map_err(|err| DocSenseError::parse(file_path, format, format!("{member}: {err}")))?;
+        }
+        _ => return Err(DocSenseError::UnsupportedFormat { path: file_path.to_path_buf() }),
+    }
+
+    read_member_contents(file_path, member, &source)
+}
+
+/* Same as for the files of a directory, hidden members (e.g. the ._ files of macOS) are left out and
+   those without an extension are told by their contents */
+fn is_indexed_member(member: &str, options: &ParseOptions) -> bool {
+    let member = Path::new(member);
+    let hidden = member.components().any(|part| matches!(part, Component::Normal(name) if name.to_string_lossy().starts_with('.')));
+    !hidden && !is_archive(member) && (inner_extension(member).is_none() || is_supported(member, options))
+}
+
+/* The member decompressed if it is compressed itself, e.g. docs/spec.txt.gz */
+fn read_member_contents(file_path: &Path, member: &str, source: &[u8]) -> Result<Vec<u8>, DocSenseError> {
+    let suffix = Path::new(member).extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+    let mut contents = Vec::new();
+    decompress(Path::new(member), source).read_to_end(&mut contents)
+        .map_err(|err| DocSenseError::parse(&member_path(file_path, member), suffix, err))?;
+    Ok(contents)
+}
+
+fn parse_member(file_path: &Path, member: &str, source: &[u8], options: &ParseOptions) -> Result<Vec<ParsedDocument>, DocSenseError> {
+    let contents = read_member_contents(file_path, member, source)?;
+    // Members of no known format and archives inside the archive are left out
+    let Some(ext) = resolve_format(inner_extension(Path::new(member)), &contents, options).filter(|ext| !ARCHIVE_EXTENSIONS.contains(ext)) else {
+        return Ok(Vec::new());
+    };
+    // Errors name the member by its virtual path
+    let documents = parse_source(&member_path(file_path, member), ext, &contents, options)?;
+    Ok(documents.into_iter().map(|document| ParsedDocument { member: Some(member.to_owned()), ..document }).collect())
+}
@@ -0,0 +1,111 @@
+use std::path::Path;
+
+use crate::error::DocSenseError;
+use super::encoding::decode_text;
+use super::{Fields, ParsedDocument, TextKind};
+
+/* Extensions of the source files DocSense knows the comment and string syntax of */
+pub const SOURCE_EXTENSIONS: [&str; 8] = ["rs", "py", "c", "h", "js", "ts", "go", "java"];
+
+/* How comments and string literals are written in a language */
+struct Syntax {
+    language: &'static str,
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [&'static str],    // Longest first, so """ is not taken for an empty string
+    lifetimes: bool,                    // A single quote may start a Rust lifetime instead of a char
+}
+
+fn syntax(ext: &str) -> Option<Syntax> {
+    let (language, line_comments, block_comment, quotes, lifetimes): (_, &'static [&'static str], _, &'static [&'static str], _) = match ext {
+        "rs" => ("rust", &["//"], Some(("/*", "*/")), &["\""], true),
+        "py" => ("python", &["#"], None, &["\"\"\"", "'''", "\"", "'"], false),
+        "c" | "h" => ("c", &["//"], Some(("/*", "*/")), &["\"", "'"], false),
+        "js" => ("javascript", &["//"], Some(("/*", "*/")), &["\"", "'", "`"], false),
+        "ts" => ("typescript", &["//"], Some(("/*", "*/")), &["\"", "'", "`"], false),
+        "go" => ("go", &["//"], Some(("/*", "*/")), &["\"", "'", "`"], false),
+        "java" => ("java", &["//"], Some(("/*", "*/")), &["\"\"\"", "\"", "'"], false),
+        _ => return None,
+    };
+    Some(Syntax { language, line_comments, block_comment, quotes, lifetimes })
+}
+
+/* Parse a source file. The text is the whole source, lexed as code so identifiers match by each
+   of their words too. Comments and string literals, the prose of a source file, are also
+   kept in the `comments` field, and the language in the `language` field. */
+pub fn parse_source_file(file_path: &Path, source: &[u8], ext: &str) -> Result<ParsedDocument, DocSenseError> {
+    let syntax = syntax(ext).ok_or_else(|| DocSenseError::UnsupportedFormat { path: file_path.to_path_buf() })?;
+    let decoded = decode_text(source);
+    let text = decoded.text;
+
+    let mut fields = Fields::new();
+    fields.insert("language".to_owned(), syntax.language.to_owned());
+    let comments = comments_and_strings(&text, &syntax);
+    if !comments.is_empty() {
+        fields.insert("comments".to_owned(), comments.join("\n"));
+    }
+    Ok(ParsedDocument { text, fields, kind: TextKind::Code, lossy: decoded.lossy, ..Default::default() })
+}
+
+/* Contents of the comments and string literals of a source, in order. A rough scanner rather than
+   a parser for every language, it only has to tell where comments and strings start and end. */
+fn comments_and_strings(source: &str, syntax: &Syntax) -> Vec<String> {
+    let mut found = Vec::new();
+    let mut rest = source;
+
+    while let Some(c) = rest.chars().next() {
+        if let Some(marker) = syntax.line_comments.iter().find(|marker| rest.starts_with(**marker)) {
+            let end = rest.find('\n').unwrap_or(rest.len());
+            push_trimmed(&mut found, &rest[marker.len()..end]);
+            rest = &rest[end..];
+        } else if let Some((open, close)) = syntax.block_comment.filter(|(open, _)| rest.starts_with(open)) {
+            let body = &rest[open.len()..];
+            let end = body.find(close).unwrap_or(body.len());
+            push_trimmed(&mut found, &body[..end]);
+            rest = &body[(end + close.len()).min(body.len())..];
+        } else if syntax.lifetimes && c == '\'' {
+            // A char literal is 'x' or an escape like '\n', anything else is a lifetime
+            let literal = match rest[1..].chars().next() {
+                Some('\\') => rest[2..].find('\'').map(|end| end + 3),
+                Some(next) => rest[1 + next.len_utf8()..].starts_with('\'').then(|| 2 + next.len_utf8()),
+                None => None,
+            };
+            rest = &rest[literal.unwrap_or(1)..];
+        } else if let Some(quote) = syntax.quotes.iter().find(|quote| rest.starts_with(**quote)) {
+            let body = &rest[quote.len()..];
+            let end = string_end(body, quote);
+            push_trimmed(&mut found, &body[..end]);
+            rest = &body[(end + quote.len()).min(body.len())..];
+        } else {
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    found
+}
+
+/* Byte offset of the quote closing a string, skipping escaped chars */
+fn string_end(body: &str, quote: &str) -> usize {
+    let mut chars = body.char_indices();
+    while let Some((i, c)) = chars.next() {
+        if c == '\\' {
+            chars.next();
+        } else if body[i..].starts_with(quote) {
+            return i;
+        } else if c == '\n' && quote.len() == 1 && quote != "`" {
+            // An unterminated string ends with its line
+            return i;
+        }
+    }
+    body.len()
+}
+
+fn push_trimmed(found: &mut Vec<String>, text: &str) {
+    // Leading stars and slashes of doc comments are decoration, not text
+    let lines = text.lines()
+        .map(|line| line.trim().trim_start_matches(['*', '/', '!']).trim())
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>();
+    if !lines.is_empty() {
+        found.push(lines.join("\n"));
+    }
+}
@@ -0,0 +1,63 @@
+use std::{fs::{self, File}, io::{BufReader, Read}, path::Path};
+
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use xz2::read::XzDecoder;
+
+use crate::error::DocSenseError;
+
+/* Suffixes of the compression formats a file is read through, e.g. spec.txt.gz or dump.xml.xz */
+pub const COMPRESSION_EXTENSIONS: [&str; 3] = ["gz", "bz2", "xz"];
+
+/* Compression suffixes of a file name, the last one first */
+fn compression_suffixes(file_path: &Path) -> Vec<&str> {
+    let mut name = file_path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+    let mut suffixes = Vec::new();
+    while let Some((stem, ext)) = name.rsplit_once('.').filter(|(_, ext)| COMPRESSION_EXTENSIONS.contains(ext)) {
+        suffixes.push(ext);
+        name = stem;
+    }
+    suffixes
+}
+
+/* Extension of what the file holds once decompressed, e.g. txt for spec.txt.gz */
+pub fn inner_extension(file_path: &Path) -> Option<&str> {
+    let mut name = file_path.file_name()?.to_str()?;
+    while let Some((stem, ext)) = name.rsplit_once('.') {
+        if !COMPRESSION_EXTENSIONS.contains(&ext) {
+            return Some(ext);
+        }
+        name = stem;
+    }
+    None
+}
+
+/* Reader decompressing `source`, the contents of the file at `file_path`, once for every compression
+   suffix of its name, starting with the last one. It is decompressed as it is read. */
+pub fn decompress<'a>(file_path: &Path, source: impl Read + 'a) -> Box<dyn Read + 'a> {
+    let mut reader: Box<dyn Read> = Box::new(source);
+    for ext in compression_suffixes(file_path) {
+        reader = match ext {
+            "gz" => Box::new(MultiGzDecoder::new(reader)),
+            "bz2" => Box::new(MultiBzDecoder::new(reader)),
+            _ => Box::new(XzDecoder::new_multi_decoder(reader)),
+        };
+    }
+    reader
+}
+
+/* Same as `decompress` for the file itself, so its compressed contents are never held in memory */
+pub fn open_decompressed(file_path: &Path) -> Result<Box<dyn Read>, DocSenseError> {
+    let file = File::open(file_path).map_err(|err| DocSenseError::io(file_path, err))?;
+    Ok(decompress(file_path, BufReader::new(file)))
+}
+
+/* Contents of the file, decompressed if it is compressed */
+pub fn read_decompressed(file_path: &Path) -> Result<Vec<u8>, DocSenseError> {
+    let Some(&outermost) = compression_suffixes(file_path).first() else {
+        return fs::read(file_path).map_err(|err| DocSenseError::io(file_path, err));
+    };
+    let mut contents = Vec::new();
+    open_decompressed(file_path)?.read_to_end(&mut contents).map_err(|err| DocSenseError::parse(file_path, outermost, err))?;
+    Ok(contents)
+}
@@ -0,0 +1,178 @@
+use std::{
+    io::{Read, Write},
+    path::Path,
+    process::{Child, Command, Stdio},
+    sync::{atomic::{AtomicBool, Ordering}, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+use tempfile::NamedTempFile;
+
+use crate::error::DocSenseError;
+use super::compression::inner_extension;
+
+/* Stands for the path of the file in the command of a converter */
+const PATH_PLACEHOLDER: &str = "{}";
+
+/* How often a running converter is checked on */
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/* At most this much of what a failing converter printed to stderr is reported */
+const MAX_STDERR: u64 = 4096;
+
+/* An external command whose output is indexed in place of files of some format, so formats can be
+   added without building them in, e.g. `pandoc -t plain {}` for .rst files. It is set up in the
+   config file (see `Config`) as a [[converter]] table, and used through `ParseOptions`. */
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Converter {
+    /* Extensions of the files it converts, e.g. ["rst", "org"] */
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    /* MIME types of the files it converts, e.g. ["application/rtf"], guessed from their extension */
+    #[serde(default)]
+    pub mime_types: Vec<String>,
+    /* Program and arguments, split like a shell would. `{}` is replaced by the path of the file,
+       without it the contents of the file are written to the stdin of the command. */
+    pub command: String,
+    /* Seconds the command may run before it is killed */
+    #[serde(default = "default_timeout")]
+    pub timeout: u64,
+    /* Bytes the command may print before it is killed */
+    #[serde(default = "default_max_output")]
+    pub max_output: u64,
+    /* Extension of the format the command prints, whose parser reads it, e.g. "html" */
+    #[serde(default = "default_output")]
+    pub output: String,
+}
+
+fn default_timeout() -> u64 {
+    30
+}
+
+fn default_max_output() -> u64 {
+    16 * 1024 * 1024
+}
+
+fn default_output() -> String {
+    "txt".to_owned()
+}
+
+impl Converter {
+    /* Whether it converts files with the extension `ext` */
+    pub fn handles(&self, ext: &str) -> bool {
+        let ext = ext.to_lowercase();
+        self.extensions.iter().any(|handled| handled.trim_start_matches('.').eq_ignore_ascii_case(&ext))
+            || mime_guess::from_ext(&ext).iter().any(|mime| self.mime_types.iter().any(|handled| handled.eq_ignore_ascii_case(mime.essence_str())))
+    }
+
+    /* The program and arguments of the command, None when it is empty or its quotes are unbalanced */
+    pub fn args(&self) -> Option<Vec<String>> {
+        shlex::split(&self.command).filter(|args| !args.is_empty())
+    }
+
+    /* Run the command on `source`, the contents of the file at `file_path` in the format `ext`, and
+       return what it printed. It fails when the command can not be started, exits with an error,
+       runs out of time or prints more than it may. */
+    pub fn convert(&self, file_path: &Path, ext: &str, source: &[u8]) -> Result<Vec<u8>, DocSenseError> {
+        let failed = |message: String| DocSenseError::parse(file_path, ext, message);
+        let args = self.args().ok_or_else(|| failed(format!("converter command `{}` is invalid", self.command)))?;
+        let program = &args[0];
+
+        // The file itself is passed when the command asks for a path, unless only its decompressed
+        // contents or those of an archive member are readable as the format
+        let takes_path = args.iter().any(|arg| arg.contains(PATH_PLACEHOLDER));
+        let temp_file = match takes_path && (!file_path.is_file() || inner_extension(file_path) != file_path.extension().and_then(|ext| ext.to_str())) {
+            true => Some(temp_copy(ext, source).map_err(|err| failed(format!("could not write a copy for `{program}`: {err}")))?),
+            false => None,
+        };
+        let input_path = temp_file.as_ref().map_or(file_path, NamedTempFile::path);
+
+        let mut child = Command::new(program)
+            .args(args[1..].iter().map(|arg| arg.replace(PATH_PLACEHOLDER, &input_path.to_string_lossy())))
+            .stdin(if takes_path { Stdio::null() } else { Stdio::piped() })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| failed(format!("could not run `{program}`: {err}")))?;
+
+        // Pipes are written and read on their own threads so a full one never blocks the command
+        let stdin = child.stdin.take().map(|mut stdin| {
+            let source = source.to_vec();
+            // A command may exit without reading all of it
+            thread::spawn(move || { let _ = stdin.write_all(&source); })
+        });
+        let max_output = self.max_output;
+        let overflowed = Arc::new(AtomicBool::new(false));
+        let stdout = child.stdout.take().map(|stdout| {
+            let overflowed = Arc::clone(&overflowed);
+            thread::spawn(move || {
+                let mut output = Vec::new();
+                stdout.take(max_output + 1).read_to_end(&mut output)?;
+                overflowed.store(output.len() as u64 > max_output, Ordering::Relaxed);
+                Ok::<_, std::io::Error>(output)
+            })
+        });
+        let stderr = child.stderr.take().map(|stderr| thread::spawn(move || {
+            let mut output = Vec::new();
+            let _ = stderr.take(MAX_STDERR).read_to_end(&mut output);
+            output
+        }));
+
+        let deadline = Instant::now() + Duration::from_secs(self.timeout);
+        let status = loop {
+            match child.try_wait().map_err(|err| failed(format!("could not wait for `{program}`: {err}")))? {
+                Some(status) => break status,
+                None if Instant::now() >= deadline => {
+                    kill(&mut child);
+                    return Err(failed(format!("`{program}` did not finish within {}s", self.timeout)));
+                }
+                // Having printed too much it may be stuck on a full pipe, which is no longer read
+                None if overflowed.load(Ordering::Relaxed) => {
+                    kill(&mut child);
+                    return Err(failed(format!("`{program}` printed more than {max_output} bytes")));
+                }
+                None => thread::sleep(POLL_INTERVAL),
+            }
+        };
+
+        if let Some(stdin) = stdin {
+            let _ = stdin.join();
+        }
+        let output = match stdout.map(|stdout| stdout.join()) {
+            Some(Ok(Ok(output))) => output,
+            Some(Ok(Err(err))) => return Err(failed(format!("could not read the output of `{program}`: {err}"))),
+            _ => Vec::new(),
+        };
+        let stderr = stderr.and_then(|stderr| stderr.join().ok()).unwrap_or_default();
+
+        // Cut off, the command may have died of the closed pipe instead of exiting on its own
+        if overflowed.load(Ordering::Relaxed) {
+            return Err(failed(format!("`{program}` printed more than {max_output} bytes")));
+        }
+        if !status.success() {
+            let stderr = String::from_utf8_lossy(&stderr);
+            return Err(match stderr.lines().map(str::trim).find(|line| !line.is_empty()) {
+                Some(reason) => failed(format!("`{program}` failed with {status}: {reason}")),
+                None => failed(format!("`{program}` failed with {status}")),
+            });
+        }
+        Ok(output)
+    }
+}
+
+fn kill(child: &mut Child) {
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/* Copy of a file in the temporary directory, removed again once dropped. It gets a new random name
+   and is created exclusively, so nothing placed there before (e.g. a symlink) is written through. */
+fn temp_copy(ext: &str, contents: &[u8]) -> std::io::Result<NamedTempFile> {
+    let mut file = tempfile::Builder::new().prefix("docsense-").suffix(&format!(".{ext}")).tempfile()?;
+    file.write_all(contents)?;
+    file.flush()?;
+    Ok(file)
+}
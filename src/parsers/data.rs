@@ -0,0 +1,225 @@
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::error::DocSenseError;
+use super::encoding::decode_text;
+use super::{Fields, ParsedDocument};
+
+/* Extensions of the formats read with the `DataSettings` */
+pub const DATA_EXTENSIONS: [&str; 5] = ["csv", "tsv", "json", "jsonl", "ndjson"];
+
+/* How tables (.csv, .tsv) and JSON (.json, .jsonl) are indexed. It is set up in the config of a
+   directory (see `Config`) as its [data] table, and handed to the parsers with the `ParseOptions`. */
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DataSettings {
+    /* Index every row of a table, every line of JSON Lines and every element of a JSON array as a
+       document of its own, located by its number, instead of the whole file as one document */
+    #[serde(default)]
+    pub records: bool,
+    /* Names of the columns and keys whose values become fields, e.g. ["title", "author"]. Every
+       column and key does when it is empty, the values of all of them are indexed as text anyway. */
+    #[serde(default)]
+    pub fields: Vec<String>,
+}
+
+impl DataSettings {
+    /* The field the values of the column or key `name` go to, if any */
+    fn field(&self, name: &str) -> Option<String> {
+        let field = field_name(name)?;
+        match self.fields.is_empty() || self.fields.iter().any(|wanted| field_name(wanted).as_ref() == Some(&field)) {
+            true => Some(field),
+            false => None,
+        }
+    }
+}
+
+/* Name of a column or key as a field (see `is_field_name`), e.g. `first_name` of "First Name" or
+   `q1` of "Q1". Anything in front of its first letter is left out, None for a name without any. */
+pub fn field_name(name: &str) -> Option<String> {
+    let mut field = String::new();
+    for c in name.trim().chars() {
+        match c {
+            c if c.is_ascii_alphabetic() => field.push(c.to_ascii_lowercase()),
+            c if c.is_ascii_digit() && !field.is_empty() => field.push(c),
+            _ if !field.is_empty() && !field.ends_with('_') => field.push('_'),
+            _ => {}
+        }
+    }
+    let field = field.trim_end_matches('_');
+    (!field.is_empty()).then(|| field.to_owned())
+}
+
+/* Values of a row or record, or of a whole file, collected as text and fields */
+#[derive(Default)]
+struct Record {
+    text: String,
+    fields: Fields,
+}
+
+impl Record {
+    /* Append a value to the text after `separator`, and to `field` if it goes to one */
+    fn add(&mut self, field: Option<&str>, value: &str, separator: char) {
+        let value = value.trim();
+        if value.is_empty() {
+            return;
+        }
+        if !self.text.is_empty() && !self.text.ends_with('\n') {
+            self.text.push(separator);
+        }
+        self.text.push_str(value);
+
+        if let Some(field) = field {
+            let values = self.fields.entry(field.to_owned()).or_default();
+            if !values.is_empty() {
+                values.push('\n');
+            }
+            values.push_str(value);
+        }
+    }
+
+    /* Start the values added next on a line of their own */
+    fn end_line(&mut self) {
+        if !self.text.is_empty() && !self.text.ends_with('\n') {
+            self.text.push('\n');
+        }
+    }
+
+    fn into_document(self, locator: Option<String>, lossy: bool) -> ParsedDocument {
+        ParsedDocument { text: self.text.trim_end().to_owned(), fields: self.fields, locator, lossy, ..Default::default() }
+    }
+}
+
+/* Parse a table of comma (.csv) or tab (.tsv) separated values whose first row names its columns.
+   The values of a column become the field it is named after. Split into records every row is its
+   own document, located by its number below the header, e.g. `sales.csv#row=12`. */
+pub fn parse_table_file(file_path: &Path, source: &[u8], ext: &str, settings: &DataSettings) -> Result<Vec<ParsedDocument>, DocSenseError> {
+    let failed = |err: csv::Error| DocSenseError::parse(file_path, ext, err.to_string());
+    let decoded = decode_text(source);
+
+    // Tab separated values have no quoting, a quote there is part of the value
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(if ext == "tsv" { b'\t' } else { b',' })
+        .quoting(ext != "tsv")
+        .flexible(true)
+        .from_reader(decoded.text.as_bytes());
+    let header = reader.headers().map_err(failed)?.clone();
+    let columns: Vec<Option<String>> = header.iter().map(|name| settings.field(name)).collect();
+
+    // The whole table keeps its header, so the names of its columns are found too
+    let mut table = Record::default();
+    for name in &header {
+        table.add(None, name, '\t');
+    }
+
+    let mut documents = Vec::new();
+    for (number, row) in reader.records().enumerate() {
+        let row = row.map_err(failed)?;
+        let mut record = Record::default();
+        let target = if settings.records { &mut record } else { &mut table };
+        target.end_line();
+        for (i, value) in row.iter().enumerate() {
+            target.add(columns.get(i).and_then(Option::as_deref), value, '\t');
+        }
+        if settings.records && !record.text.is_empty() {
+            documents.push(record.into_document(Some(format!("row={}", number + 1)), decoded.lossy));
+        }
+    }
+
+    if !settings.records {
+        return Ok(vec![table.into_document(None, decoded.lossy)]);
+    }
+    if documents.is_empty() {
+        return Err(DocSenseError::parse(file_path, ext, "it holds no rows"));
+    }
+    Ok(documents)
+}
+
+/* Parse a JSON document. Its strings and numbers are indexed, each under the field of the key
+   they are the value of, however deep it is nested. Split into records every element of an array
+   at the top is its own document, located by its number, e.g. `dump.json#record=3`. */
+pub fn parse_json_file(file_path: &Path, source: &[u8], settings: &DataSettings) -> Result<Vec<ParsedDocument>, DocSenseError> {
+    let decoded = decode_text(source);
+    let value: Value = serde_json::from_str(&decoded.text).map_err(|err| DocSenseError::parse(file_path, "json", err.to_string()))?;
+
+    match value {
+        Value::Array(elements) if settings.records => {
+            let documents = elements.iter().enumerate().map(|(i, element)| {
+                let mut record = Record::default();
+                add_value(&mut record, settings, None, element);
+                record.into_document(Some(format!("record={}", i + 1)), decoded.lossy)
+            });
+            let documents: Vec<_> = documents.filter(|document| !document.text.is_empty()).collect();
+            if documents.is_empty() {
+                return Err(DocSenseError::parse(file_path, "json", "it holds no records"));
+            }
+            Ok(documents)
+        }
+        value => {
+            let mut record = Record::default();
+            add_value(&mut record, settings, None, &value);
+            Ok(vec![record.into_document(None, decoded.lossy)])
+        }
+    }
+}
+
+/* Parse JSON Lines, a JSON value on every line. Split into records every line is its own document,
+   located by its line number, e.g. `events.jsonl#line=40`. Lines which are no valid JSON (e.g. the
+   last one of a dump cut off while it was written) are left out. */
+pub fn parse_jsonl_file(file_path: &Path, source: &[u8], ext: &str, settings: &DataSettings) -> Result<Vec<ParsedDocument>, DocSenseError> {
+    let decoded = decode_text(source);
+
+    let mut file = Record::default();
+    let mut documents = Vec::new();
+    let mut valid = false;
+    for (i, line) in decoded.text.lines().enumerate() {
+        let Ok(value) = serde_json::from_str::<Value>(line) else { continue };
+        valid = true;
+        match settings.records {
+            true => {
+                let mut record = Record::default();
+                add_value(&mut record, settings, None, &value);
+                if !record.text.is_empty() {
+                    documents.push(record.into_document(Some(format!("line={}", i + 1)), decoded.lossy));
+                }
+            }
+            false => {
+                file.end_line();
+                add_value(&mut file, settings, None, &value);
+            }
+        }
+    }
+
+    if !valid && !decoded.text.trim().is_empty() {
+        return Err(DocSenseError::parse(file_path, ext, "none of its lines is valid JSON"));
+    }
+    if !settings.records {
+        return Ok(vec![file.into_document(None, decoded.lossy)]);
+    }
+    if documents.is_empty() {
+        return Err(DocSenseError::parse(file_path, ext, "it holds no records"));
+    }
+    Ok(documents)
+}
+
+/* Add the strings and numbers of a JSON value to the record, under the field of the innermost key
+   they belong to. Booleans and nulls tell nothing searchable. */
+fn add_value(record: &mut Record, settings: &DataSettings, field: Option<&str>, value: &Value) {
+    match value {
+        Value::String(string) => record.add(field, string, '\n'),
+        Value::Number(number) => record.add(field, &number.to_string(), '\n'),
+        Value::Array(elements) => {
+            for element in elements {
+                add_value(record, settings, field, element);
+            }
+        }
+        Value::Object(entries) => {
+            for (key, value) in entries {
+                add_value(record, settings, settings.field(key).as_deref(), value);
+            }
+        }
+        Value::Bool(_) | Value::Null => {}
+    }
+}
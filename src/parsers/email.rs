@@ -0,0 +1,77 @@
+use std::path::Path;
+
+use mail_parser::{mailbox::mbox::MessageIterator, Address, Message, MessageParser};
+
+use crate::error::DocSenseError;
+use super::{html, Fields, ParsedDocument};
+
+/* Parse a single RFC 5322 message (.eml) */
+pub fn parse_eml_file(file_path: &Path, source: &[u8]) -> Result<ParsedDocument, DocSenseError> {
+    let message = MessageParser::default().parse(source)
+        .ok_or_else(|| DocSenseError::parse(file_path, "eml", "it holds no message headers"))?;
+    Ok(parse_message(&message))
+}
+
+/* Parse an mbox mailbox. Every message is its own document, located by its number in the mailbox,
+   e.g. `inbox.mbox#message=3`. Messages which can not be parsed are left out. */
+pub fn parse_mbox_file(file_path: &Path, source: &[u8]) -> Result<Vec<ParsedDocument>, DocSenseError> {
+    let parser = MessageParser::default();
+
+    let mut documents = Vec::new();
+    for (number, entry) in MessageIterator::new(source).enumerate() {
+        let entry = entry.map_err(|err| DocSenseError::io(file_path, err))?;
+        let Some(message) = parser.parse(entry.contents()) else { continue };
+        let document = parse_message(&message);
+        documents.push(ParsedDocument { locator: Some(format!("message={}", number + 1)), ..document });
+    }
+
+    if documents.is_empty() {
+        return Err(DocSenseError::parse(file_path, "mbox", "it holds no messages"));
+    }
+    Ok(documents)
+}
+
+/* Text of the message body, with the subject in front of it same as the title of other documents,
+   and its From, To, Subject and Date headers as fields. Of a body sent both as plain text and as
+   HTML the plain text is read, HTML only bodies go through the HTML parser. */
+fn parse_message(message: &Message) -> ParsedDocument {
+    let mut fields = Fields::new();
+    for (field, address) in [("from", message.from()), ("to", message.to())] {
+        let addresses = address.map(addresses).unwrap_or_default();
+        if !addresses.is_empty() {
+            fields.insert(field.to_owned(), addresses);
+        }
+    }
+    if let Some(subject) = message.subject().map(str::trim).filter(|subject| !subject.is_empty()) {
+        fields.insert("subject".to_owned(), subject.to_owned());
+    }
+    if let Some(date) = message.date() {
+        fields.insert("date".to_owned(), date.to_rfc3339());
+    }
+
+    let mut text = fields.get("subject").map(|subject| format!("{subject}\n")).unwrap_or_default();
+    for part in message.text_bodies() {
+        let Some(contents) = part.text_contents() else { continue };
+        let body = match part.is_text_html() {
+            true => html::parse_html_content(contents).text,
+            false => contents.trim().to_owned(),
+        };
+        if !body.is_empty() {
+            text.push_str(&body);
+            text.push('\n');
+        }
+    }
+    ParsedDocument { text: text.trim_end().to_owned(), fields, ..Default::default() }
+}
+
+/* Every address as `Name <address>`, one per line */
+fn addresses(address: &Address) -> String {
+    address.iter()
+        .map(|addr| match (addr.name(), addr.address()) {
+            (Some(name), Some(address)) => format!("{name} <{address}>"),
+            (name, address) => name.or(address).unwrap_or_default().to_owned(),
+        })
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
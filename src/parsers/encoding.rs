@@ -0,0 +1,87 @@
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+
+/* Text of a file transcoded to UTF-8 */
+pub struct DecodedText {
+    pub text: String,
+    pub lossy: bool,    // Some bytes were no valid text in the encoding and were replaced by U+FFFD
+}
+
+/* Decode a text file whose encoding is declared nowhere but in its bytes, see `detect_encoding` */
+pub fn decode_text(source: &[u8]) -> DecodedText {
+    decode(source, detect_encoding(source))
+}
+
+/* Decode an XML document by its byte order mark, or else the encoding named in its prolog, e.g.
+   <?xml version="1.0" encoding="ISO-8859-1"?>. Without either it is guessed like for text. */
+pub fn decode_xml(source: &[u8]) -> DecodedText {
+    let encoding = Encoding::for_bom(source).map(|(encoding, _)| encoding)
+        .or_else(|| utf16_without_bom(source))
+        .or_else(|| declared_encoding(source))
+        .unwrap_or_else(|| detect_encoding(source));
+    decode(source, encoding)
+}
+
+/* Encoding of text by its bytes alone. A byte order mark decides, UTF-16 is also recognised by
+   its zero bytes. Text which is valid UTF-8, or damaged in just a few places, is read as UTF-8,
+   anything else as Windows-1252, the superset of Latin-1 most legacy Western text is in. */
+pub fn detect_encoding(source: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(source) {
+        return encoding;
+    }
+    utf16_without_bom(source).unwrap_or(if is_mostly_utf8(source) { UTF_8 } else { WINDOWS_1252 })
+}
+
+/* UTF-16 text of mostly ASCII chars without a byte order mark, told by the zero byte in every other place */
+pub fn utf16_without_bom(source: &[u8]) -> Option<&'static Encoding> {
+    let sample = &source[..source.len().min(512) & !1];
+    if sample.len() < 8 {
+        return None;
+    }
+    let zeros_at = |offset: usize| sample.iter().skip(offset).step_by(2).filter(|&&byte| byte == 0).count();
+    let half = sample.len() / 2;
+    match (zeros_at(0), zeros_at(1)) {
+        (even, odd) if odd * 10 >= half * 9 && even * 20 <= half => Some(UTF_16LE),
+        (even, odd) if even * 10 >= half * 9 && odd * 20 <= half => Some(UTF_16BE),
+        _ => None,
+    }
+}
+
+/* Transcode to UTF-8, dropping a byte order mark. A byte order mark wins over `encoding`. */
+fn decode(source: &[u8], encoding: &'static Encoding) -> DecodedText {
+    let (text, _, lossy) = encoding.decode(source);
+    DecodedText { text: text.into_owned(), lossy }
+}
+
+/* Whether the text is UTF-8 with more valid multi-byte chars than broken ones. Legacy 8 bit text
+   hardly ever holds a valid multi-byte sequence, UTF-8 cut off at the end has a broken last char. */
+fn is_mostly_utf8(source: &[u8]) -> bool {
+    let non_ascii = |text: &str| text.chars().filter(|c| !c.is_ascii()).count();
+    let (mut valid, mut broken) = (0, 0);
+    let mut rest = source;
+    loop {
+        match std::str::from_utf8(rest) {
+            Ok(text) => return broken == 0 || valid + non_ascii(text) > broken,
+            Err(err) => {
+                let (good, bad) = rest.split_at(err.valid_up_to());
+                valid += std::str::from_utf8(good).map_or(0, non_ascii);
+                broken += 1;
+                rest = &bad[err.error_len().unwrap_or(bad.len())..];
+            }
+        }
+    }
+}
+
+/* Encoding named in the prolog of an XML document. A prolog is written in ASCII whatever the
+   encoding of the rest is, UTF-16 aside, which `decode_xml` recognises before. */
+fn declared_encoding(source: &[u8]) -> Option<&'static Encoding> {
+    let start = &source[..source.len().min(1024)];
+    let start = std::str::from_utf8(start).unwrap_or_else(|err| std::str::from_utf8(&start[..err.valid_up_to()]).unwrap_or_default());
+    let prolog = start.trim_start().strip_prefix("<?xml")?;
+    let prolog = &prolog[..prolog.find("?>")?];
+
+    let value = prolog[prolog.find("encoding")? + "encoding".len()..].trim_start().strip_prefix('=')?.trim_start();
+    let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let label = &value[1..][..value[1..].find(quote)?];
+    // A prolog claiming UTF-16 in single bytes is wrong about it
+    Encoding::for_label(label.trim().as_bytes()).filter(|encoding| encoding.is_ascii_compatible())
+}
@@ -0,0 +1,157 @@
+use std::{collections::HashMap, path::Path};
+
+use xml::{reader::XmlEvent, EventReader};
+
+use crate::error::DocSenseError;
+use super::encoding::decode_xml;
+use super::html::parse_html_content;
+use super::package::{open_package, read_required_part};
+use super::ParsedDocument;
+
+/* Where every EPUB names its package document */
+const CONTAINER_PATH: &str = "META-INF/container.xml";
+
+/* What the package document (.opf) of a book says about it */
+#[derive(Default)]
+struct Book {
+    title: Option<String>,
+    authors: Vec<String>,
+    manifest: HashMap<String, String>,  // Item id -> href, relative to the package document
+    spine: Vec<String>,                 // Item ids of the chapters in reading order
+}
+
+/* Parse an EPUB book into one document per chapter of its spine, so a hit points at the chapter.
+   Each chapter gets the title and authors of the book as fields, besides its own headings. */
+pub fn parse_epub_file(file_path: &Path, source: &[u8]) -> Result<Vec<ParsedDocument>, DocSenseError> {
+    let mut archive = open_package(file_path, "epub", source)?;
+
+    let container = read_required_part(&mut archive, CONTAINER_PATH, file_path, "epub")?;
+    let package_path = rootfile(&container).ok_or_else(|| DocSenseError::parse(file_path, "epub", "the container names no package document"))?;
+    let package = parse_package(&read_required_part(&mut archive, &package_path, file_path, "epub")?)
+        .map_err(|err| DocSenseError::parse(file_path, "epub", format!("{package_path}: {err}")))?;
+
+    // Hrefs of the manifest are relative to the package document
+    let base = package_path.rfind('/').map_or("", |slash| &package_path[..=slash]);
+
+    let mut chapters = Vec::new();
+    for id in &package.spine {
+        let Some(href) = package.manifest.get(id) else { continue };
+        let chapter_path = resolve(base, &percent_decode(href.split('#').next().unwrap_or_default()));
+        let source = read_required_part(&mut archive, &chapter_path, file_path, "epub")?;
+
+        // Chapters are XHTML, which may declare an encoding other than UTF-8 in its prolog
+        let decoded = decode_xml(&source);
+        let mut chapter = ParsedDocument { lossy: decoded.lossy, ..parse_html_content(&decoded.text) };
+        if chapter.text.trim().is_empty() {
+            continue;
+        }
+
+        // The chapter is named by its first heading, or its own <title> when it has none
+        let page_title = chapter.fields.remove("title");
+        let heading = chapter.fields.get("headings").and_then(|headings| headings.lines().next()).map(str::to_owned);
+        if let Some(name) = heading.or(page_title) {
+            chapter.fields.insert("chapter".to_owned(), name);
+        }
+        if let Some(title) = &package.title {
+            chapter.fields.insert("title".to_owned(), title.clone());
+        }
+        if !package.authors.is_empty() {
+            chapter.fields.insert("author".to_owned(), package.authors.join("\n"));
+        }
+        chapter.locator = Some(chapter_path);
+        chapters.push(chapter);
+    }
+    Ok(chapters)
+}
+
+/* Path of the package document, the full-path of the first <rootfile> of container.xml */
+fn rootfile(container: &[u8]) -> Option<String> {
+    for event in EventReader::new(container) {
+        if let XmlEvent::StartElement { name, attributes, .. } = event.ok()? {
+            if name.local_name == "rootfile" {
+                return attributes.into_iter().find(|attribute| attribute.name.local_name == "full-path").map(|attribute| attribute.value);
+            }
+        }
+    }
+    None
+}
+
+fn parse_package(source: &[u8]) -> Result<Book, xml::reader::Error> {
+    let mut package = Book::default();
+    let mut in_metadata = false;
+    let mut current = None;     // Metadata element whose text is being read
+    let mut text = String::new();
+
+    for event in EventReader::new(source) {
+        match event? {
+            XmlEvent::StartElement { name, attributes, .. } => {
+                let attribute = |wanted: &str| attributes.iter().find(|attribute| attribute.name.local_name == wanted).map(|attribute| attribute.value.clone());
+                match name.local_name.as_str() {
+                    "metadata" => in_metadata = true,
+                    "title" | "creator" if in_metadata => {
+                        current = Some(name.local_name.clone());
+                        text.clear();
+                    }
+                    "item" => {
+                        if let (Some(id), Some(href)) = (attribute("id"), attribute("href")) {
+                            package.manifest.insert(id, href);
+                        }
+                    }
+                    "itemref" => package.spine.extend(attribute("idref")),
+                    _ => {}
+                }
+            }
+            XmlEvent::Characters(characters) | XmlEvent::CData(characters) if current.is_some() => text.push_str(&characters),
+            XmlEvent::EndElement { name } => {
+                if name.local_name == "metadata" {
+                    in_metadata = false;
+                }
+                if current.as_deref() == Some(name.local_name.as_str()) {
+                    let value = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                    match current.take().as_deref() {
+                        // A book may have several titles, the first one is the main title
+                        Some("title") if package.title.is_none() && !value.is_empty() => package.title = Some(value),
+                        Some("creator") if !value.is_empty() => package.authors.push(value),
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(package)
+}
+
+/* Join a relative href onto the directory of the package document, resolving `.` and `..` */
+fn resolve(base: &str, href: &str) -> String {
+    let mut segments: Vec<&str> = base.split('/').filter(|segment| !segment.is_empty()).collect();
+    for segment in href.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => { segments.pop(); }
+            _ => segments.push(segment),
+        }
+    }
+    segments.join("/")
+}
+
+/* Hrefs are URLs, so names with spaces and the like are percent encoded in them */
+fn percent_decode(href: &str) -> String {
+    let bytes = href.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| std::str::from_utf8(hex).ok()).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,169 @@
+use scraper::{ElementRef, Html, Node};
+
+use super::encoding::decode_text;
+use super::{Fields, ParsedDocument};
+
+/* Elements holding scripts, styling or navigation boilerplate rather than content */
+const SKIPPED_ELEMENTS: [&str; 8] = ["script", "style", "noscript", "template", "svg", "nav", "footer", "aside"];
+
+/* ARIA roles marking the same boilerplate on generic elements, e.g. <div role="navigation"> */
+const SKIPPED_ROLES: [&str; 3] = ["navigation", "contentinfo", "complementary"];
+
+/* Elements flowing along with the surrounding text, any other element starts a line of its own */
+const INLINE_ELEMENTS: [&str; 27] = [
+    "a", "abbr", "b", "bdi", "bdo", "cite", "code", "data", "del", "dfn", "em", "font", "i", "ins",
+    "kbd", "label", "mark", "q", "s", "samp", "small", "span", "strong", "sub", "sup", "time", "u",
+];
+
+/* Meta tags of the head kept as fields of the same name */
+const META_FIELDS: [&str; 3] = ["author", "description", "keywords"];
+
+/* Parse an HTML page. Malformed markup is repaired the way browsers do it instead of failing */
+pub fn parse_html_file(source: &[u8]) -> ParsedDocument {
+    let decoded = decode_text(source);
+    ParsedDocument { lossy: decoded.lossy, ..parse_html(&decoded.text) }
+}
+
+/* Visible text of the page with entities decoded, and its title, headings and meta tags as fields.
+   The title is also put in front of the text, so plain queries find the page by it. */
+pub fn parse_html(source: &str) -> ParsedDocument {
+    let mut document = parse_html_content(source);
+    if let Some(title) = document.fields.get("title") {
+        document.text = format!("{title}\n{text}", text = document.text);
+    }
+    document
+}
+
+/* Same as `parse_html` without the title in front of the text, for pages which are only part of
+   a document with a title of its own, e.g. the chapters of a book */
+pub fn parse_html_content(source: &str) -> ParsedDocument {
+    let page = Html::parse_document(source);
+    let mut extractor = Extractor::default();
+    extractor.walk(page.root_element());
+
+    let text = extractor.text.trim_end().to_owned();
+
+    let mut fields = extractor.fields;
+    if !extractor.title.is_empty() {
+        fields.insert("title".to_owned(), extractor.title);
+    }
+    if !extractor.headings.is_empty() {
+        fields.insert("headings".to_owned(), extractor.headings.join("\n"));
+    }
+    ParsedDocument { text, fields, ..Default::default() }
+}
+
+/* A step of the walk through the page, see `Extractor::walk` */
+enum Step<'a> {
+    Enter(ElementRef<'a>),
+    Text(&'a str),
+    Leave { name: &'a str, start: usize, block: bool },   // Past the children of an element
+}
+
+#[derive(Default)]
+struct Extractor {
+    text: String,
+    title: String,
+    headings: Vec<String>,
+    fields: Fields,
+}
+
+impl Extractor {
+    /* Collect the text and fields of the element and everything inside it. The elements are
+       walked with a stack of their own, as a page may nest them deeper than recursion could go. */
+    fn walk(&mut self, root: ElementRef) {
+        let mut steps = vec![Step::Enter(root)];
+        while let Some(step) = steps.pop() {
+            match step {
+                Step::Enter(element) => self.enter(element, &mut steps),
+                Step::Text(text) => self.push_text(text),
+                Step::Leave { name, start, block } => self.leave(name, start, block),
+            }
+        }
+    }
+
+    /* Start on an element, its children and the end of it are walked through next */
+    fn enter<'a>(&mut self, element: ElementRef<'a>, steps: &mut Vec<Step<'a>>) {
+        let name = element.value().name();
+        match name {
+            "title" => {
+                if self.title.is_empty() {
+                    self.title = element.text().flat_map(str::split_whitespace).collect::<Vec<_>>().join(" ");
+                }
+                return;
+            }
+            "meta" => {
+                let meta_name = element.value().attr("name").map(str::to_lowercase);
+                if let (Some(meta_name), Some(content)) = (meta_name, element.value().attr("content")) {
+                    if META_FIELDS.contains(&meta_name.as_str()) && !content.trim().is_empty() {
+                        self.fields.insert(meta_name, content.trim().to_owned());
+                    }
+                }
+                return;
+            }
+            _ => {}
+        }
+
+        let role = element.value().attr("role");
+        if SKIPPED_ELEMENTS.contains(&name) || role.is_some_and(|role| SKIPPED_ROLES.contains(&role)) {
+            return;
+        }
+
+        let block = !INLINE_ELEMENTS.contains(&name);
+        if block {
+            self.line_break();
+        }
+        let start = self.text.len();
+
+        steps.push(Step::Leave { name, start, block });
+        steps.extend(element.children().rev().filter_map(|child| match child.value() {
+            Node::Text(text) => Some(Step::Text(text)),
+            Node::Element(_) => ElementRef::wrap(child).map(Step::Enter),
+            _ => None,
+        }));
+    }
+
+    /* Finish an element whose text starts at `start` */
+    fn leave(&mut self, name: &str, start: usize, block: bool) {
+        if matches!(name, "h1" | "h2" | "h3" | "h4" | "h5" | "h6") {
+            let heading = self.text[start..].trim();
+            if !heading.is_empty() {
+                self.headings.push(heading.to_owned());
+            }
+        }
+        if block {
+            self.line_break();
+        }
+    }
+
+    /* Append text with every run of whitespace collapsed into a single space, as a browser shows it */
+    fn push_text(&mut self, text: &str) {
+        for c in text.chars() {
+            if !c.is_whitespace() {
+                self.text.push(c);
+            } else if !self.text.is_empty() && !self.text.ends_with(char::is_whitespace) {
+                self.text.push(' ');
+            }
+        }
+    }
+
+    fn line_break(&mut self) {
+        let length = self.text.trim_end_matches(' ').len();
+        self.text.truncate(length);
+        if !self.text.is_empty() && !self.text.ends_with('\n') {
+            self.text.push('\n');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_deeply_parses() {
+        let depth = 5_000;
+        let document = parse_html(&format!("<title>Deep</title>{}text{}", "<div>".repeat(depth), "</div>".repeat(depth)));
+        assert_eq!(document.text, "Deep\ntext");
+    }
+}
@@ -0,0 +1,770 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::error::DocSenseError;
+use super::encoding::decode_text;
+use super::{Fields, ParsedDocument};
+
+/* Commands titling a part of the document, kept in the `headings` field */
+const SECTIONING_COMMANDS: [&str; 7] = ["part", "chapter", "section", "subsection", "subsubsection", "paragraph", "subparagraph"];
+
+/* Commands whose first arguments are no text, e.g. the label of \href{url}{label} is kept but not the
+   url. `usize::MAX` drops every argument following the command, e.g. of \newcommand{\name}[1]{body}. */
+const DROPPED_ARGUMENTS: [(&str, usize); 39] = [
+    ("label", 1), ("ref", 1), ("eqref", 1), ("pageref", 1), ("autoref", 1), ("cref", 1), ("Cref", 1),
+    ("cite", 1), ("citep", 1), ("citet", 1), ("citeauthor", 1), ("citeyear", 1), ("nocite", 1),
+    ("includegraphics", 1), ("usepackage", 1), ("documentclass", 1), ("bibliography", 1), ("bibliographystyle", 1),
+    ("input", 1), ("include", 1), ("url", 1), ("href", 1), ("graphicspath", 1), ("hypersetup", 1),
+    ("vspace", 1), ("hspace", 1), ("pagestyle", 1), ("thispagestyle", 1), ("thanks", 1), ("date", 1),
+    ("color", 1), ("textcolor", 1), ("setlength", 2), ("setcounter", 2), ("addtocounter", 2),
+    ("newcommand", usize::MAX), ("renewcommand", usize::MAX), ("providecommand", usize::MAX), ("newenvironment", usize::MAX),
+];
+
+/* Environments of math or drawings, left out entirely */
+const MATH_ENVIRONMENTS: [&str; 9] = ["equation", "align", "alignat", "gather", "multline", "eqnarray", "displaymath", "math", "tikzpicture"];
+
+/* Environments whose content is kept as it is written */
+const VERBATIM_ENVIRONMENTS: [&str; 4] = ["verbatim", "lstlisting", "minted", "comment"];
+
+/* Environments taking arguments which are no text right after \begin, e.g. the columns of a tabular */
+const ENVIRONMENT_ARGUMENTS: [(&str, usize); 6] = [("tabular", 1), ("tabularx", 2), ("array", 1), ("minipage", 1), ("multicols", 1), ("minted", 1)];
+
+/* How deep commands may be nested in the arguments of each other, e.g. \section{\'{\c{c}}}.
+   Reading an argument recurses, so deeper ones are read as plain groups to not run out of stack. */
+const MAX_COMMAND_DEPTH: usize = 64;
+
+/* Months BibTeX knows by their abbreviation */
+const MONTHS: [(&str, &str); 12] = [
+    ("jan", "January"), ("feb", "February"), ("mar", "March"), ("apr", "April"), ("may", "May"), ("jun", "June"),
+    ("jul", "July"), ("aug", "August"), ("sep", "September"), ("oct", "October"), ("nov", "November"), ("dec", "December"),
+];
+
+/* Parse a LaTeX document. Commands, math and comments are left out and only what the typeset
+   document reads is indexed. Its title, authors, the headings of its sections, the captions of
+   its figures and tables and the abstract are also kept as fields, the title in front of the
+   text too same as for other documents. Only the body is read when there is one, the preamble
+   just for the title and authors. */
+pub fn parse_tex_file(source: &[u8]) -> ParsedDocument {
+    let decoded = decode_text(source);
+    let source = decoded.text.as_str();
+
+    let mut extractor = Extractor::default();
+    let body = match source.find("\\begin{document}") {
+        Some(start) => {
+            extractor.convert(&source[..start]);
+            let body = &source[start + "\\begin{document}".len()..];
+            &body[..body.find("\\end{document}").unwrap_or(body.len())]
+        }
+        None => source,
+    };
+    let text = extractor.convert(body);
+
+    let mut fields = Fields::new();
+    for (field, values) in [("title", extractor.title), ("author", extractor.authors), ("headings", extractor.headings), ("captions", extractor.captions), ("abstract", extractor.abstracts)] {
+        let values = values.into_iter().filter(|value| !value.is_empty()).collect::<Vec<_>>();
+        if !values.is_empty() {
+            fields.insert(field.to_owned(), values.join("\n"));
+        }
+    }
+    let text = match fields.get("title") {
+        Some(title) => format!("{title}\n{text}"),
+        None => text,
+    };
+    ParsedDocument { text, fields, lossy: decoded.lossy, ..Default::default() }
+}
+
+/* Parse a BibTeX database. Every entry is its own document, located by its citation key, e.g.
+   `refs.bib#vaswani2017`, with its title, authors (one per line), year, abstract and keywords as
+   fields and its type, e.g. article. @string abbreviations are expanded, @comment and @preamble
+   blocks left out, same as entries which are not well formed. */
+pub fn parse_bib_file(file_path: &Path, source: &[u8]) -> Result<Vec<ParsedDocument>, DocSenseError> {
+    let decoded = decode_text(source);
+    let mut reader = BibReader { chars: decoded.text.chars().collect(), pos: 0 };
+    let mut strings = MONTHS.iter().map(|(abbreviation, month)| (abbreviation.to_string(), month.to_string())).collect::<HashMap<_, _>>();
+
+    let mut documents = Vec::new();
+    while reader.skip_to('@') {
+        reader.pos += 1;
+        let Some(entry) = reader.entry(&strings) else { continue };
+        match entry.kind.as_str() {
+            "comment" | "preamble" => {}
+            "string" => strings.extend(entry.values.into_iter().map(|(name, value)| (name.to_lowercase(), value))),
+            _ => documents.push(entry_document(entry, decoded.lossy)),
+        }
+    }
+
+    if documents.is_empty() {
+        return Err(DocSenseError::parse(file_path, "bib", "it holds no entries"));
+    }
+    Ok(documents)
+}
+
+fn entry_document(entry: BibEntry, lossy: bool) -> ParsedDocument {
+    let mut fields = Fields::new();
+    fields.insert("type".to_owned(), entry.kind);
+    for (name, value) in entry.values {
+        let value = Extractor::default().convert(&value).replace('\n', " ");
+        let value = match name.as_str() {
+            "title" | "year" | "abstract" | "keywords" => value,
+            "author" => value.split(" and ").map(str::trim).filter(|author| !author.is_empty()).collect::<Vec<_>>().join("\n"),
+            _ => continue,
+        };
+        if !value.is_empty() {
+            fields.insert(name, value);
+        }
+    }
+
+    let text = ["title", "author", "abstract", "keywords"].iter()
+        .filter_map(|field| fields.get(*field))
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .join("\n");
+    ParsedDocument { text, fields, locator: Some(entry.key), lossy, ..Default::default() }
+}
+
+/* Turns LaTeX into the text it typesets, collecting the parts kept as fields on the way */
+#[derive(Default)]
+struct Extractor {
+    title: Vec<String>,
+    authors: Vec<String>,
+    headings: Vec<String>,
+    captions: Vec<String>,
+    abstracts: Vec<String>,
+    depth: usize,       // Number of commands whose arguments are being read, see MAX_COMMAND_DEPTH
+}
+
+impl Extractor {
+    /* Text of the LaTeX source, a line per paragraph */
+    fn convert(&mut self, source: &str) -> String {
+        let mut reader = TexReader { chars: source.chars().collect(), pos: 0 };
+        let mut text = String::new();
+        self.read(&mut reader, &mut text, false);
+        tidy(&text)
+    }
+
+    /* Append the text of the source to `out` up to its end, or the brace closing the group being read */
+    fn read(&mut self, reader: &mut TexReader, out: &mut String, in_group: bool) {
+        // Groups inside only scope formatting, so they are counted rather than read on their own
+        let mut groups = 0;
+        while let Some(c) = reader.next() {
+            match c {
+                '%' => reader.skip_line(),
+                '{' => groups += 1,
+                '}' if groups > 0 => groups -= 1,
+                '}' if in_group => return,
+                '}' => {}
+                '$' => reader.skip_math(),
+                // A tie, or a tab between the columns of a table (a literal & is escaped)
+                '~' | '&' => out.push(' '),
+                '\\' => self.command(reader, out),
+                c => out.push(c),
+            }
+        }
+    }
+
+    /* Text of the next argument of a command: a group, or else a single char */
+    fn argument(&mut self, reader: &mut TexReader) -> String {
+        let mut out = String::new();
+        reader.skip_spaces();
+        match reader.next() {
+            Some('{') => self.read(reader, &mut out, true),
+            Some('\\') => self.command(reader, &mut out),
+            Some(c) => out.push(c),
+            None => {}
+        }
+        tidy(&out).replace('\n', " ")
+    }
+
+    fn command(&mut self, reader: &mut TexReader, out: &mut String) {
+        if self.depth == MAX_COMMAND_DEPTH {
+            // Only the name is skipped, the arguments are read as the groups they are
+            if reader.next().is_some_and(|first| first.is_ascii_alphabetic()) {
+                while reader.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+                    reader.pos += 1;
+                }
+            }
+            return;
+        }
+        self.depth += 1;
+        self.read_command(reader, out);
+        self.depth -= 1;
+    }
+
+    fn read_command(&mut self, reader: &mut TexReader, out: &mut String) {
+        let Some(first) = reader.next() else { return };
+        if !first.is_ascii_alphabetic() {
+            match first {
+                '\\' => {
+                    out.push('\n');
+                    reader.skip_optional();
+                }
+                '[' => reader.skip_until("\\]"),
+                '(' => reader.skip_until("\\)"),
+                '\'' | '`' | '^' | '"' | '~' | '=' | '.' => {
+                    let letter = self.argument(reader);
+                    out.push_str(&accented(first, &letter));
+                }
+                ',' | ';' | ':' | ' ' | '\n' => out.push(' '),
+                '-' | '/' | '@' | '!' => {}
+                c => out.push(c),   // An escaped char, e.g. \% or \&
+            }
+            return;
+        }
+
+        let mut name = String::from(first);
+        while let Some(c) = reader.peek().filter(char::is_ascii_alphabetic) {
+            name.push(c);
+            reader.pos += 1;
+        }
+        if reader.peek() == Some('*') {
+            reader.pos += 1;
+        }
+        // Same as TeX the spaces after a command word are no text, e.g. Stra\ss e
+        while reader.peek().is_some_and(|c| c == ' ' || c == '\t') {
+            reader.pos += 1;
+        }
+
+        match name.as_str() {
+            "begin" => self.environment(reader, out),
+            "end" => {
+                reader.argument_source();
+                out.push('\n');
+            }
+            "title" => {
+                reader.skip_optional();
+                let title = self.argument(reader);
+                self.title.push(title);
+            }
+            "author" => {
+                reader.skip_optional();
+                // The authors are told apart by the \and between them in the source, which is no text
+                match reader.argument_source() {
+                    Some(source) => {
+                        for author in split_authors(&source) {
+                            let author = self.convert(&author).replace('\n', " ");
+                            if !author.is_empty() {
+                                self.authors.push(author);
+                            }
+                        }
+                    }
+                    None => {
+                        let author = self.argument(reader);
+                        self.authors.push(author);
+                    }
+                }
+            }
+            "and" => out.push(' '),
+            "abstract" => {
+                let abstract_text = self.argument(reader);
+                out.push_str(&format!("\n{abstract_text}\n"));
+                self.abstracts.push(abstract_text);
+            }
+            "caption" => {
+                reader.skip_optional();
+                let caption = self.argument(reader);
+                out.push_str(&format!("\n{caption}\n"));
+                self.captions.push(caption);
+            }
+            _ if SECTIONING_COMMANDS.contains(&name.as_str()) => {
+                reader.skip_optional();
+                let heading = self.argument(reader);
+                out.push_str(&format!("\n{heading}\n"));
+                self.headings.push(heading);
+            }
+            "def" => {
+                // \def\name#1{body}
+                reader.skip_to('{');
+                reader.argument_source();
+            }
+            "item" | "par" | "newline" | "linebreak" | "newpage" | "clearpage" | "maketitle" => out.push('\n'),
+            "ss" => out.push('ß'),
+            "ae" => out.push('æ'),
+            "AE" => out.push('Æ'),
+            "oe" => out.push('œ'),
+            "OE" => out.push('Œ'),
+            "o" => out.push('ø'),
+            "O" => out.push('Ø'),
+            "aa" => out.push('å'),
+            "AA" => out.push('Å'),
+            "l" => out.push('ł'),
+            "L" => out.push('Ł'),
+            "i" => out.push('i'),
+            "j" => out.push('j'),
+            "LaTeX" | "TeX" => out.push_str(&name),
+            "c" => {
+                let letter = self.argument(reader);
+                out.push_str(&accented('c', &letter));
+            }
+            _ => {
+                let dropped = DROPPED_ARGUMENTS.iter().find(|(command, _)| *command == name).map_or(0, |(_, count)| *count);
+                for _ in 0..dropped {
+                    reader.skip_optional();
+                    if reader.argument_source().is_none() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /* An environment, after its \begin. Most are read like the rest of the text, with their
+       arguments left out, math is skipped and verbatim text kept as it is. */
+    fn environment(&mut self, reader: &mut TexReader, out: &mut String) {
+        let Some(name) = reader.argument_source() else { return };
+        let base = name.trim_end_matches('*');
+        let end = format!("\\end{{{name}}}");
+
+        if MATH_ENVIRONMENTS.contains(&base) {
+            reader.skip_until(&end);
+            return;
+        }
+
+        reader.skip_optional();
+        let arguments = ENVIRONMENT_ARGUMENTS.iter().find(|(environment, _)| *environment == base).map_or(0, |(_, count)| *count);
+        for _ in 0..arguments {
+            reader.skip_optional();
+            reader.argument_source();
+        }
+
+        if VERBATIM_ENVIRONMENTS.contains(&base) {
+            let content = reader.source_until(&end);
+            if base != "comment" {
+                out.push('\n');
+                out.push_str(&content);
+                out.push('\n');
+            }
+        } else if base == "abstract" {
+            let mut abstract_text = String::new();
+            let mut inner = TexReader { chars: reader.source_until(&end).chars().collect(), pos: 0 };
+            self.read(&mut inner, &mut abstract_text, false);
+            let abstract_text = tidy(&abstract_text);
+            out.push_str(&format!("\n{abstract_text}\n"));
+            self.abstracts.push(abstract_text.replace('\n', " "));
+        } else {
+            out.push('\n');
+        }
+    }
+}
+
+/* A char of LaTeX source and the position in it */
+struct TexReader {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl TexReader {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    /* Step over the next char, staying at the end of the source when there is none, e.g. for an
+       escaped char after a backslash which ends the source */
+    fn advance(&mut self) {
+        self.pos = (self.pos + 1).min(self.chars.len());
+    }
+
+    fn starts_with(&self, text: &str) -> bool {
+        text.chars().enumerate().all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+
+    /* Skip spaces and at most one line break, a blank line ends a paragraph */
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(|c| c == ' ' || c == '\t') {
+            self.pos += 1;
+        }
+        if self.peek() == Some('\n') && self.chars.get(self.pos + 1) != Some(&'\n') {
+            self.pos += 1;
+            while self.peek().is_some_and(|c| c == ' ' || c == '\t') {
+                self.pos += 1;
+            }
+        }
+    }
+
+    /* A comment takes the line break along */
+    fn skip_line(&mut self) {
+        while let Some(c) = self.next() {
+            if c == '\n' {
+                break;
+            }
+        }
+    }
+
+    fn skip_to(&mut self, c: char) {
+        while self.peek().is_some_and(|next| next != c) {
+            self.pos += 1;
+        }
+    }
+
+    /* Skip past `end`, or to the end of the source when it never comes */
+    fn skip_until(&mut self, end: &str) {
+        self.source_until(end);
+    }
+
+    /* The source up to `end`, which is skipped as well */
+    fn source_until(&mut self, end: &str) -> String {
+        let start = self.pos;
+        while self.pos < self.chars.len() && !self.starts_with(end) {
+            self.pos += 1;
+        }
+        let source = self.chars[start..self.pos].iter().collect();
+        self.pos = (self.pos + end.chars().count()).min(self.chars.len());
+        source
+    }
+
+    /* Skip inline math, $...$, or display math, $$...$$ */
+    fn skip_math(&mut self) {
+        if self.peek() == Some('$') {
+            self.pos += 1;
+            self.skip_until("$$");
+            return;
+        }
+        while let Some(c) = self.next() {
+            match c {
+                '\\' => self.advance(),
+                '$' => break,
+                _ => {}
+            }
+        }
+    }
+
+    /* Skip an optional argument in brackets, e.g. the short title of \section[short]{long} */
+    fn skip_optional(&mut self) {
+        let start = self.pos;
+        self.skip_spaces();
+        if self.peek() != Some('[') {
+            self.pos = start;
+            return;
+        }
+        let mut depth = 0;
+        while let Some(c) = self.next() {
+            match c {
+                '[' | '{' => depth += 1,
+                ']' | '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                '\\' => self.advance(),
+                _ => {}
+            }
+        }
+    }
+
+    /* The source of the group following a command, None when there is none */
+    fn argument_source(&mut self) -> Option<String> {
+        let start = self.pos;
+        self.skip_spaces();
+        if self.peek() != Some('{') {
+            self.pos = start;
+            return None;
+        }
+        self.pos += 1;
+        let group_start = self.pos;
+        let mut depth = 1;
+        while let Some(c) = self.next() {
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(self.chars[group_start..self.pos - 1].iter().collect());
+                    }
+                }
+                '\\' => self.advance(),
+                _ => {}
+            }
+        }
+        Some(self.chars[group_start..].iter().collect())
+    }
+}
+
+/* An entry of a BibTeX database, e.g. @article{key, title = {...}} */
+struct BibEntry {
+    kind: String,
+    key: String,
+    values: Vec<(String, String)>,
+}
+
+struct BibReader {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl BibReader {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    /* Same as `TexReader::advance` */
+    fn advance(&mut self) {
+        self.pos = (self.pos + 1).min(self.chars.len());
+    }
+
+    fn skip_to(&mut self, c: char) -> bool {
+        while self.peek().is_some_and(|next| next != c) {
+            self.pos += 1;
+        }
+        self.peek().is_some()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn word(&mut self) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| !c.is_whitespace() && !"{}()=,#\"@".contains(c)) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    /* The entry after an @, None when it is not well formed */
+    fn entry(&mut self, strings: &HashMap<String, String>) -> Option<BibEntry> {
+        let kind = self.word().to_lowercase();
+        self.skip_whitespace();
+        let close = match self.peek()? {
+            '{' => '}',
+            '(' => ')',
+            _ => return None,
+        };
+        self.pos += 1;
+
+        if kind == "comment" || kind == "preamble" {
+            self.pos -= 1;
+            self.balanced()?;
+            return Some(BibEntry { kind, key: String::new(), values: Vec::new() });
+        }
+
+        let mut key = String::new();
+        if kind != "string" {
+            self.skip_whitespace();
+            key = self.word();
+            self.skip_whitespace();
+            if self.peek() == Some(',') {
+                self.pos += 1;
+            }
+        }
+
+        let mut values = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek()? {
+                c if c == close => {
+                    self.pos += 1;
+                    break;
+                }
+                ',' => {
+                    self.pos += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            let name = self.word().to_lowercase();
+            self.skip_whitespace();
+            if name.is_empty() || self.peek()? != '=' {
+                return None;
+            }
+            self.pos += 1;
+            values.push((name, self.value(strings)?));
+        }
+        (kind == "string" || !key.is_empty()).then_some(BibEntry { kind, key, values })
+    }
+
+    /* A value made up of quoted or braced parts, numbers and @string abbreviations joined by # */
+    fn value(&mut self, strings: &HashMap<String, String>) -> Option<String> {
+        let mut value = String::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek()? {
+                '{' => value.push_str(&self.balanced()?),
+                '"' => {
+                    self.pos += 1;
+                    let start = self.pos;
+                    let mut depth = 0;
+                    while let Some(c) = self.peek() {
+                        match c {
+                            '{' => depth += 1,
+                            '}' => depth -= 1,
+                            '"' if depth == 0 => break,
+                            '\\' => self.advance(),
+                            _ => {}
+                        }
+                        self.advance();
+                    }
+                    value.extend(&self.chars[start..self.pos]);
+                    // The closing quote, unless the source ends before it
+                    self.advance();
+                }
+                _ => {
+                    let word = self.word();
+                    if word.is_empty() {
+                        return None;
+                    }
+                    value.push_str(strings.get(&word.to_lowercase()).unwrap_or(&word));
+                }
+            }
+            self.skip_whitespace();
+            if self.peek() != Some('#') {
+                return Some(value);
+            }
+            self.pos += 1;
+        }
+    }
+
+    /* Contents of the braced or parenthesised group at the position, without the outer delimiters */
+    fn balanced(&mut self) -> Option<String> {
+        let open = self.peek()?;
+        let close = if open == '(' { ')' } else { '}' };
+        self.pos += 1;
+        let start = self.pos;
+        let mut depth = 1;
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            if c == open {
+                depth += 1;
+            } else if c == close {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(self.chars[start..self.pos - 1].iter().collect());
+                }
+            }
+        }
+        None
+    }
+}
+
+/* A letter with the accent of a command like \'e or \"{o}, the letter alone when there is no such char */
+fn accented(accent: char, letter: &str) -> String {
+    let combining = match accent {
+        '\'' => '\u{301}',
+        '`' => '\u{300}',
+        '^' => '\u{302}',
+        '"' => '\u{308}',
+        '~' => '\u{303}',
+        '=' => '\u{304}',
+        '.' => '\u{307}',
+        'c' => '\u{327}',
+        _ => return letter.to_owned(),
+    };
+    let mut chars = letter.chars();
+    let Some(base) = chars.next() else { return String::new() };
+    match compose(base, combining) {
+        Some(composed) => std::iter::once(composed).chain(chars).collect(),
+        None => letter.to_owned(),
+    }
+}
+
+/* The precomposed form of the Latin letters accents are usually put on */
+fn compose(base: char, combining: char) -> Option<char> {
+    const TABLE: [(char, &str, &str); 8] = [
+        ('\u{301}', "aeiouyAEIOUYcnszCNSZ", "áéíóúýÁÉÍÓÚÝćńśźĆŃŚŹ"),
+        ('\u{300}', "aeiouAEIOU", "àèìòùÀÈÌÒÙ"),
+        ('\u{302}', "aeiouAEIOU", "âêîôûÂÊÎÔÛ"),
+        ('\u{308}', "aeiouyAEIOUY", "äëïöüÿÄËÏÖÜŸ"),
+        ('\u{303}', "anoANO", "ãñõÃÑÕ"),
+        ('\u{304}', "aeiouAEIOU", "āēīōūĀĒĪŌŪ"),
+        ('\u{307}', "zZ", "żŻ"),
+        ('\u{327}', "cCsS", "çÇşŞ"),
+    ];
+    let (_, bases, composed) = TABLE.iter().find(|(accent, _, _)| *accent == combining)?;
+    bases.chars().position(|c| c == base).and_then(|i| composed.chars().nth(i))
+}
+
+/* Source of each author of \author{}, which are separated by \and */
+fn split_authors(source: &str) -> Vec<String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut authors = vec![String::new()];
+    let mut i = 0;
+    while i < chars.len() {
+        let and = chars[i] == '\\'
+            && chars[i + 1..].starts_with(&['a', 'n', 'd'])
+            && !chars.get(i + 4).is_some_and(char::is_ascii_alphabetic);
+        if and {
+            authors.push(String::new());
+            i += 4;
+            continue;
+        }
+        // An escaped char is kept with its backslash, e.g. \\ ending a line
+        let end = match chars[i] {
+            '\\' => (i + 2).min(chars.len()),
+            _ => i + 1,
+        };
+        authors.last_mut().unwrap().extend(&chars[i..end]);
+        i = end;
+    }
+    authors
+}
+
+/* Text with the whitespace of every line collapsed and blank lines dropped */
+fn tidy(text: &str) -> String {
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEX: &str = r#"\documentclass{article}
+\title{On \emph{Escapes}} \author{A \and B}
+\begin{document}
+\begin{abstract}Costs 5\% less, $x \$ y$ and $$a \\ b$$.\end{abstract}
+\section[Short \]]{Long \{title\}}
+\begin{minted}{py\}}print(1)\end{minted}
+\caption{A \& B} \verb|x| \href{u\}}{link}
+\end{document}
+"#;
+
+    const BIB: &str = r#"@string{j = "Journal \"of\" Tests"}
+@article{key, title = "A \"quoted\" {title}", author = {A and B}, journal = j # " \\", year = 2020}
+"#;
+
+    /* The source cut off after each of its chars, a backslash at the end included */
+    fn truncations(source: &str) -> impl Iterator<Item = &str> {
+        source.char_indices().map(|(i, c)| &source[..i + c.len_utf8()])
+    }
+
+    #[test]
+    fn tex_cut_off_anywhere_parses() {
+        for source in truncations(TEX) {
+            parse_tex_file(source.as_bytes());
+        }
+        parse_tex_file(br"\begin{document}\begin{minted}{py\");
+    }
+
+    #[test]
+    fn tex_authors_are_fields_only() {
+        let document = parse_tex_file(br"\author{Ada \textsc{Lovelace} \\ London \and Alan Turing}\begin{document}Text \and more\end{document}");
+        assert_eq!(document.fields["author"], "Ada Lovelace London\nAlan Turing");
+        assert_eq!(document.text, "Text more");
+    }
+
+    #[test]
+    fn tex_nested_deeply_parses() {
+        for source in ["{".repeat(1_000_000), "\\section{".repeat(100_000), "\\'".repeat(100_000), "\\author{\\and".repeat(100_000)] {
+            parse_tex_file(format!("{source}x").as_bytes());
+        }
+        let document = parse_tex_file(format!("\\section{{{}Deep{}}}", "\\emph{".repeat(1000), "}".repeat(1000)).as_bytes());
+        assert_eq!(document.fields["headings"], "Deep");
+    }
+
+    #[test]
+    fn bib_cut_off_anywhere_parses() {
+        for source in truncations(BIB) {
+            let _ = parse_bib_file(Path::new("refs.bib"), source.as_bytes());
+        }
+        let _ = parse_bib_file(Path::new("refs.bib"), br#"@misc{key, title = "ends in \"#);
+    }
+}
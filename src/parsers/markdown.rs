@@ -0,0 +1,166 @@
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, MetadataBlockKind, Options, Parser, Tag, TagEnd};
+use serde_yaml::Value;
+
+use crate::lexer::is_field_name;
+use super::encoding::decode_text;
+use super::{Fields, ParsedDocument};
+
+/* Parse a Markdown file. Link targets, image sources, raw HTML and the markers of the syntax are
+   left out, so only what a reader of the rendered page sees is indexed. */
+pub fn parse_markdown_file(source: &[u8]) -> ParsedDocument {
+    let decoded = decode_text(source);
+    ParsedDocument { lossy: decoded.lossy, ..parse_markdown(&decoded.text) }
+}
+
+/* Text of the document and its fields: the headings, the text of its links, its fenced code
+   blocks and their languages, and every entry of the YAML front matter (e.g. title, tags, date).
+   The title, from the front matter or else the first top level heading, is also put in front
+   of the text, same as for HTML pages. Front matter which is no valid YAML is left out. */
+pub fn parse_markdown(source: &str) -> ParsedDocument {
+    let options = Options::ENABLE_YAML_STYLE_METADATA_BLOCKS | Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let mut extractor = Extractor::default();
+    for event in Parser::new_ext(source, options) {
+        extractor.event(event);
+    }
+
+    let mut fields = extractor.fields;
+    for (field, values) in [("headings", extractor.headings), ("links", extractor.links), ("code", extractor.code), ("language", extractor.languages)] {
+        if !values.is_empty() {
+            fields.insert(field.to_owned(), values.join("\n"));
+        }
+    }
+
+    // A title taken from the first heading is in the text already
+    let mut text = extractor.text.trim_end().to_owned();
+    match (fields.get("title"), extractor.first_heading) {
+        (Some(title), _) => text.insert_str(0, &format!("{title}\n")),
+        (None, Some(heading)) => { fields.insert("title".to_owned(), heading); }
+        (None, None) => {}
+    }
+    ParsedDocument { text, fields, ..Default::default() }
+}
+
+#[derive(Default)]
+struct Extractor {
+    text: String,
+    fields: Fields,                 // Entries of the front matter
+    headings: Vec<String>,
+    first_heading: Option<String>,  // First top level heading, the title when the front matter has none
+    links: Vec<String>,
+    code: Vec<String>,
+    languages: Vec<String>,
+    // Start in `text` of the heading, link or code block being read, and the front matter
+    heading: Option<(usize, bool)>,
+    link: Option<usize>,
+    code_block: Option<usize>,
+    front_matter: Option<String>,
+}
+
+impl Extractor {
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(Tag::MetadataBlock(MetadataBlockKind::YamlStyle)) => self.front_matter = Some(String::new()),
+            Event::End(TagEnd::MetadataBlock(_)) => {
+                if let Some(front_matter) = self.front_matter.take() {
+                    self.fields = front_matter_fields(&front_matter).unwrap_or_default();
+                }
+            }
+            Event::Text(text) if self.front_matter.is_some() => self.front_matter.as_mut().unwrap().push_str(&text),
+
+            Event::Start(Tag::Heading { level, .. }) => {
+                self.line_break();
+                self.heading = Some((self.text.len(), level == HeadingLevel::H1));
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some((start, top_level)) = self.heading.take() {
+                    let heading = self.text[start..].trim().to_owned();
+                    if !heading.is_empty() {
+                        if top_level && self.first_heading.is_none() {
+                            self.first_heading = Some(heading.clone());
+                        }
+                        self.headings.push(heading);
+                    }
+                }
+                self.line_break();
+            }
+            Event::Start(Tag::Link { .. }) => self.link = Some(self.text.len()),
+            Event::End(TagEnd::Link) => {
+                if let Some(start) = self.link.take() {
+                    let link = self.text[start..].trim();
+                    if !link.is_empty() {
+                        self.links.push(link.to_owned());
+                    }
+                }
+            }
+            Event::Start(Tag::CodeBlock(kind)) => {
+                self.line_break();
+                self.code_block = Some(self.text.len());
+                // The info string of a fence may hold attributes after the language, e.g. ```rust,ignore
+                if let CodeBlockKind::Fenced(info) = kind {
+                    let language = info.split(|c: char| c.is_whitespace() || c == ',' || c == '{').next().unwrap_or_default().to_lowercase();
+                    if !language.is_empty() && !self.languages.contains(&language) {
+                        self.languages.push(language);
+                    }
+                }
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                if let Some(start) = self.code_block.take() {
+                    let code = self.text[start..].trim_end();
+                    if !code.is_empty() {
+                        self.code.push(code.to_owned());
+                    }
+                }
+                self.line_break();
+            }
+            Event::Start(Tag::Paragraph | Tag::Item | Tag::BlockQuote(_) | Tag::TableRow | Tag::TableHead) => self.line_break(),
+            Event::End(TagEnd::Paragraph | TagEnd::Item | TagEnd::BlockQuote(_) | TagEnd::TableRow | TagEnd::TableHead) => self.line_break(),
+            Event::End(TagEnd::TableCell) => self.text.push(' '),
+
+            Event::Text(text) | Event::Code(text) | Event::InlineMath(text) | Event::DisplayMath(text) => self.text.push_str(&text),
+            Event::SoftBreak => self.text.push(' '),
+            Event::HardBreak | Event::Rule => self.line_break(),
+            // Raw HTML is markup, not text
+            _ => {}
+        }
+    }
+
+    fn line_break(&mut self) {
+        let length = self.text.trim_end_matches(' ').len();
+        self.text.truncate(length);
+        if !self.text.is_empty() && !self.text.ends_with('\n') {
+            self.text.push('\n');
+        }
+    }
+}
+
+/* Every top level entry of the front matter with a plain value, or a list of them, as a field.
+   Keys are lowercased, those which can not be queried as a field name are left out. */
+fn front_matter_fields(front_matter: &str) -> Result<Fields, serde_yaml::Error> {
+    let mut fields = Fields::new();
+    let Value::Mapping(entries) = serde_yaml::from_str::<Value>(front_matter)? else { return Ok(fields) };
+
+    for (key, value) in entries {
+        let Some(key) = scalar(&key).map(|key| key.to_lowercase()) else { continue };
+        if !is_field_name(&key) {
+            continue;
+        }
+        let values = match &value {
+            Value::Sequence(items) => items.iter().filter_map(scalar).collect(),
+            value => scalar(value).into_iter().collect::<Vec<_>>(),
+        };
+        let values = values.into_iter().map(|value| value.trim().to_owned()).filter(|value| !value.is_empty()).collect::<Vec<_>>();
+        if !values.is_empty() {
+            fields.insert(key, values.join("\n"));
+        }
+    }
+    Ok(fields)
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
@@ -0,0 +1,115 @@
+use std::{io::Read, path::Path};
+
+use xml::{reader::{ParserConfig2, XmlEvent}, EventReader};
+use xml::common::{TextPosition, Position};
+
+use crate::error::DocSenseError;
+use super::encoding::decode_xml;
+use super::{Fields, ParsedDocument};
+
+/* Parse all the text (Character Events) from the XML File. It is transcoded to UTF-8 first by its
+   byte order mark or the encoding its prolog declares, which the parser is then told to ignore. */
+pub fn parse_xml_file(file_path: &Path, source: &[u8]) -> Result<ParsedDocument, DocSenseError> {
+    let decoded = decode_xml(source);
+    let config = ParserConfig2::new()
+        .override_encoding(Some(xml::Encoding::Utf8))
+        .ignore_invalid_encoding_declarations(true);
+    let er = EventReader::new_with_config(decoded.text.as_bytes(), config);
+    let mut content = String::new();
+
+    for event in er.into_iter() {
+        let event = event.map_err(|err| {
+            let TextPosition {row, column} = err.position();
+            DocSenseError::parse(file_path, "xml", format!("{row}:{column}: {msg}", msg = err.msg()))
+        })?;
+
+        if let XmlEvent::Characters(text) = event {
+            content.push_str(&text);
+            content.push(' ');
+        }
+    }
+    Ok(ParsedDocument { lossy: decoded.lossy, ..ParsedDocument::from(content) })
+}
+
+/* How the text of a document is laid out in an XML vocabulary. Elements are named without their
+   namespace prefix, e.g. "p" for the <w:p> paragraphs of Word and the <text:p> ones of OpenDocument. */
+#[derive(Default)]
+pub struct XmlLayout<'a> {
+    pub skipped: &'a [&'a str],     // Elements whose content is not part of the text, e.g. deleted revisions
+    pub lines: &'a [&'a str],       // Elements ending a line, e.g. paragraphs
+    pub spaces: &'a [&'a str],      // Elements standing for a space, e.g. tabs
+}
+
+/* The text of an XML document laid out as `layout` says. Unlike `parse_xml_file` character data
+   is joined as is, as words are often split across several elements, e.g. runs of formatting. */
+pub fn xml_text(source: impl Read, layout: &XmlLayout) -> Result<String, xml::reader::Error> {
+    let mut text = String::new();
+    let mut skipping = 0;   // Depth inside skipped elements
+
+    for event in EventReader::new(source) {
+        match event? {
+            XmlEvent::StartElement { name, .. } => {
+                let name = name.local_name.as_str();
+                if skipping > 0 || layout.skipped.contains(&name) {
+                    skipping += 1;
+                } else if layout.spaces.contains(&name) {
+                    text.push(' ');
+                }
+            }
+            XmlEvent::EndElement { name } => {
+                if skipping > 0 {
+                    skipping -= 1;
+                } else if layout.lines.contains(&name.local_name.as_str()) {
+                    let length = text.trim_end_matches(' ').len();
+                    text.truncate(length);
+                    if !text.is_empty() && !text.ends_with('\n') {
+                        text.push('\n');
+                    }
+                }
+            }
+            XmlEvent::Characters(characters) | XmlEvent::CData(characters) if skipping == 0 => text.push_str(&characters),
+            XmlEvent::Whitespace(_) if skipping == 0 && !text.is_empty() && !text.ends_with(char::is_whitespace) => text.push(' '),
+            _ => {}
+        }
+    }
+    Ok(text.trim_end().to_owned())
+}
+
+/* Collect the text of metadata elements as fields, e.g. [("creator", "author")] turns
+   <dc:creator>Jane</dc:creator> into author = Jane. Repeated elements end up on separate lines. */
+pub fn xml_fields(source: impl Read, names: &[(&str, &str)]) -> Result<Fields, xml::reader::Error> {
+    let mut fields = Fields::new();
+    let mut current: Option<(&str, String)> = None;     // Field whose element is being read
+
+    for event in EventReader::new(source) {
+        match event? {
+            XmlEvent::StartElement { name, .. } => {
+                if let Some(&(_, field)) = names.iter().find(|(element, _)| *element == name.local_name) {
+                    current = Some((field, String::new()));
+                }
+            }
+            XmlEvent::Characters(characters) | XmlEvent::CData(characters) => {
+                if let Some((_, value)) = &mut current {
+                    value.push_str(&characters);
+                }
+            }
+            XmlEvent::EndElement { name } if names.iter().any(|(element, _)| *element == name.local_name) => {
+                let Some((field, value)) = current.take() else { continue };
+                let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+                if value.is_empty() {
+                    continue;
+                }
+                fields.entry(field.to_owned())
+                    .and_modify(|values: &mut String| {
+                        if !values.lines().any(|existing| existing == value) {
+                            values.push('\n');
+                            values.push_str(&value);
+                        }
+                    })
+                    .or_insert(value);
+            }
+            _ => {}
+        }
+    }
+    Ok(fields)
+}
@@ -0,0 +1,276 @@
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use super::error::DocSenseError;
+
+mod archive;
+mod code;
+mod compression;
+mod converter;
+mod data;
+mod email;
+mod encoding;
+mod epub;
+mod html;
+mod latex;
+mod markdown;
+mod markup;
+mod odf;
+mod ooxml;
+mod package;
+mod pdf;
+mod sniff;
+mod text;
+
+pub use archive::{member_path, read_member, split_member_path};
+pub use compression::{inner_extension, read_decompressed};
+pub use converter::Converter;
+pub use data::{field_name, DataSettings};
+pub use sniff::{sniff_file, sniff_format};
+
+/* Extensions of the files DocSense knows how to read */
+pub const ALLOWED_FILE_TYPE_EXTENSIONS: [&str; 24] = [
+    "xml", "xhtml", "html", "htm", "txt", "md", "markdown", "pdf", "epub", "docx", "pptx", "xlsx", "odt", "odp", "ods", "eml", "mbox", "tex", "bib",
+    "csv", "tsv", "json", "jsonl", "ndjson",
+];
+
+/* Named parts of a document besides its text, e.g. its title or author. Each field is indexed
+   on its own, so queries can be restricted to it with `name:term`. Several values of the same
+   field (e.g. every heading) are kept on separate lines of one value. */
+pub type Fields = BTreeMap<String, String>;
+
+/* How the files of the formats whose documents depend on the `ParseOptions` are parsed, by
+   extension, see `ParseOptions::format_settings` */
+pub type FormatSettings = BTreeMap<String, String>;
+
+/* Everything a parser extracted from a document */
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct ParsedDocument {
+    pub text: String,
+    pub fields: Fields,
+    /* Where in its file the document is, for files holding several of them (e.g. a chapter of a book).
+       None for a document which is the whole file. */
+    #[serde(default)]
+    pub locator: Option<String>,
+    #[serde(default)]
+    pub kind: TextKind,
+    /* Path of the member of an archive the document was parsed from, e.g. docs/intro.md of
+       bundle.zip. None for a document which is not inside an archive. */
+    #[serde(default)]
+    pub member: Option<String>,
+    /* Whether some of the text could not be decoded and was replaced by U+FFFD. Only reported
+       while indexing, it is not stored. */
+    #[serde(skip)]
+    pub lossy: bool,
+}
+
+/* How the text of a document is broken up into terms */
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TextKind {
+    #[default]
+    Prose,
+    Code,       // Identifiers are split into their words, see `Lexer::code`
+}
+
+impl From<String> for ParsedDocument {
+    fn from(text: String) -> Self {
+        Self { text, fields: Fields::new(), locator: None, kind: TextKind::Prose, member: None, lossy: false }
+    }
+}
+
+/* Documents of a file, along with the members of an archive which could not be parsed. Those are
+   left out instead of failing the whole archive. */
+#[derive(Default)]
+pub struct ParsedFile {
+    pub documents: Vec<ParsedDocument>,
+    pub skipped: Vec<DocSenseError>,
+}
+
+impl From<Vec<ParsedDocument>> for ParsedFile {
+    fn from(documents: Vec<ParsedDocument>) -> Self {
+        Self { documents, skipped: Vec::new() }
+    }
+}
+
+/* How files are parsed besides by their format, handed to every parse function by whoever parses
+   them (e.g. an `Index`, set up from a `Config`). The default has every format read by the parser
+   built into DocSense. */
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /* External commands for other formats, see `Converter`. They take precedence over the built in
+       parsers, the first one handling an extension is used. */
+    pub converters: Vec<Converter>,
+    /* How tables and JSON are indexed, see `DataSettings` */
+    pub data: DataSettings,
+}
+
+impl ParseOptions {
+    /* The converter set up for files with the extension `ext`, if any */
+    pub fn converter_for(&self, ext: &str) -> Option<&Converter> {
+        self.converters.iter().find(|converter| converter.handles(ext))
+    }
+
+    /* How the files of every format are parsed where the options make a difference, by extension:
+       the converter handling it, or the data settings for tables and JSON when they are not the
+       default ones. Archives and files without an extension (keyed by "") may hold any format, so
+       they depend on all of them. An index records this to tell which of its files were parsed
+       with other options. */
+    pub fn format_settings(&self) -> FormatSettings {
+        let data = format!("{:?}", self.data);
+        let mut extensions = data::DATA_EXTENSIONS.map(str::to_owned).to_vec();
+        for converter in &self.converters {
+            extensions.extend(converter.extensions.iter().map(|ext| ext.trim_start_matches('.').to_lowercase()));
+            for mime in &converter.mime_types {
+                let mime_extensions = mime_guess::get_mime_extensions_str(&mime.to_lowercase()).unwrap_or_default();
+                extensions.extend(mime_extensions.iter().map(|ext| ext.to_string()));
+            }
+        }
+
+        let mut settings = FormatSettings::new();
+        for ext in extensions {
+            let parsed_with = match self.converter_for(&ext) {
+                // What a converter prints as data is read with the data settings too
+                Some(converter) if data::DATA_EXTENSIONS.contains(&converter.output.as_str()) => format!("{converter:?} {data}"),
+                Some(converter) => format!("{converter:?}"),
+                None if data::DATA_EXTENSIONS.contains(&ext.as_str()) && self.data != DataSettings::default() => data.clone(),
+                None => continue,
+            };
+            settings.insert(ext, parsed_with);
+        }
+        if !settings.is_empty() {
+            let all = format!("{self:?}");
+            for ext in archive::ARCHIVE_EXTENSIONS.into_iter().chain([""]) {
+                settings.insert(ext.to_owned(), all.clone());
+            }
+        }
+        settings
+    }
+}
+
+/* Whether there is a parser or a converter for the extension of the file, or of what it holds when it is compressed */
+pub fn is_supported(file_path: &Path, options: &ParseOptions) -> bool {
+    compression::inner_extension(file_path).is_some_and(|ext| {
+        is_built_in(ext) || archive::ARCHIVE_EXTENSIONS.contains(&ext) || options.converter_for(ext).is_some()
+    })
+}
+
+/* Whether the file can be indexed, by its extension or by its contents when it has none (e.g. README or LICENSE) */
+pub fn is_indexable(file_path: &Path, options: &ParseOptions) -> bool {
+    match compression::inner_extension(file_path) {
+        Some(_) => is_supported(file_path, options),
+        None => sniff::sniff_file(file_path).is_some(),
+    }
+}
+
+/* Whether DocSense itself has a parser for the extension `ext` */
+pub fn is_built_in(ext: &str) -> bool {
+    ALLOWED_FILE_TYPE_EXTENSIONS.contains(&ext) || code::SOURCE_EXTENSIONS.contains(&ext)
+}
+
+/* Extract the documents of a file with the parser its extension asks for. Most formats yield a
+   single document, containers like books yield one per part so search results point into them.
+   Compressed files (e.g. spec.txt.gz) are parsed as what they hold, archives member by member.
+   Files without an extension, or whose contents are plainly another format than their extension
+   says (e.g. a PDF named .txt), are parsed as the format their contents are sniffed as. */
+pub fn parse_file_by_ext(file_path: &Path, options: &ParseOptions) -> Result<ParsedFile, DocSenseError> {
+    let unsupported = || DocSenseError::UnsupportedFormat { path: file_path.to_path_buf() };
+    let declared = compression::inner_extension(file_path);
+    if declared.is_some() && !is_supported(file_path, options) {
+        return Err(unsupported());
+    }
+    // Tar archives are streamed instead of being read whole
+    if declared == Some("tar") {
+        return archive::parse_archive_file(file_path, "tar", options);
+    }
+
+    let source = compression::read_decompressed(file_path)?;
+    let ext = sniff::resolve_format(declared, &source, options).ok_or_else(unsupported)?;
+    if archive::ARCHIVE_EXTENSIONS.contains(&ext) {
+        return archive::parse_archive_source(file_path, ext, &source, options);
+    }
+    parse_source(file_path, ext, &source, options).map(ParsedFile::from)
+}
+
+/* Extract the documents of `source`, the contents of the file at `file_path` in the format `ext`.
+   A converter set up for the format is run instead of the built in parser, its output is then
+   read by the parser of the format it prints. */
+fn parse_source(file_path: &Path, ext: &str, source: &[u8], options: &ParseOptions) -> Result<Vec<ParsedDocument>, DocSenseError> {
+    match options.converter_for(ext) {
+        Some(converter) => {
+            let output = converter.convert(file_path, ext, source)?;
+            parse_built_in(file_path, &converter.output, &output, options)
+        }
+        None => parse_built_in(file_path, ext, source, options),
+    }
+}
+
+fn parse_built_in(file_path: &Path, ext: &str, source: &[u8], options: &ParseOptions) -> Result<Vec<ParsedDocument>, DocSenseError> {
+    match ext {
+        "xml" | "xhtml" => {
+            return markup::parse_xml_file(file_path, source).map(|document| vec![document]);
+        }
+
+        "html" | "htm" => {
+            return Ok(vec![html::parse_html_file(source)]);
+        }
+
+        "epub" => {
+            return epub::parse_epub_file(file_path, source);
+        }
+
+        "docx" | "pptx" | "xlsx" => {
+            return ooxml::parse_ooxml_file(file_path, source, ext).map(|document| vec![document]);
+        }
+
+        "odt" | "odp" | "ods" => {
+            return odf::parse_odf_file(file_path, source, ext).map(|document| vec![document]);
+        }
+
+        "md" | "markdown" => {
+            return Ok(vec![markdown::parse_markdown_file(source)]);
+        }
+
+        "txt" => {
+            return Ok(vec![text::parse_txt_file(source)]);
+        }
+
+        "pdf" => {
+            return pdf::parse_pdf_files(file_path, source);
+        }
+
+        "eml" => {
+            return email::parse_eml_file(file_path, source).map(|document| vec![document]);
+        }
+
+        "mbox" => {
+            return email::parse_mbox_file(file_path, source);
+        }
+
+        "tex" => {
+            return Ok(vec![latex::parse_tex_file(source)]);
+        }
+
+        "bib" => {
+            return latex::parse_bib_file(file_path, source);
+        }
+
+        "csv" | "tsv" => {
+            return data::parse_table_file(file_path, source, ext, &options.data);
+        }
+
+        "json" => {
+            return data::parse_json_file(file_path, source, &options.data);
+        }
+
+        "jsonl" | "ndjson" => {
+            return data::parse_jsonl_file(file_path, source, ext, &options.data);
+        }
+
+        _ if code::SOURCE_EXTENSIONS.contains(&ext) => {
+            return code::parse_source_file(file_path, source, ext).map(|document| vec![document]);
+        }
+
+        _ => Err(DocSenseError::UnsupportedFormat { path: file_path.to_path_buf() })
+    }
+}
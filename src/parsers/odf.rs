@@ -0,0 +1,41 @@
+use std::path::Path;
+
+use crate::error::DocSenseError;
+use super::markup::{xml_fields, xml_text, XmlLayout};
+use super::package::{open_package, read_part, read_required_part};
+use super::ParsedDocument;
+
+/* Paragraphs and headings of text documents, and the same inside the cells of spreadsheets and
+   the frames of presentations. Tracked changes hold the deleted text, which is no longer part of
+   the document. */
+const CONTENT_LAYOUT: XmlLayout = XmlLayout { skipped: &["tracked-changes"], lines: &["p", "h", "line-break"], spaces: &["s", "tab"] };
+
+/* Metadata kept as fields, by their element name. The initial creator and the last one to
+   edit the document are both taken as its authors. */
+const META_FIELDS: [(&str, &str); 6] = [
+    ("title", "title"),
+    ("initial-creator", "author"),
+    ("creator", "author"),
+    ("subject", "subject"),
+    ("description", "description"),
+    ("keyword", "keywords"),
+];
+
+/* Parse an OpenDocument text (.odt), presentation (.odp) or spreadsheet (.ods), `format` being its
+   extension. The title of its metadata is also put in front of the text, same as for HTML pages. */
+pub fn parse_odf_file(file_path: &Path, source: &[u8], format: &str) -> Result<ParsedDocument, DocSenseError> {
+    let mut package = open_package(file_path, format, source)?;
+
+    let content = read_required_part(&mut package, "content.xml", file_path, format)?;
+    let mut text = xml_text(content.as_slice(), &CONTENT_LAYOUT).map_err(|err| DocSenseError::parse(file_path, format, format!("content.xml: {err}")))?;
+
+    let fields = match read_part(&mut package, "meta.xml", file_path, format)? {
+        Some(meta) => xml_fields(meta.as_slice(), &META_FIELDS).map_err(|err| DocSenseError::parse(file_path, format, format!("meta.xml: {err}")))?,
+        None => Default::default(),
+    };
+
+    if let Some(title) = fields.get("title") {
+        text.insert_str(0, &format!("{title}\n"));
+    }
+    Ok(ParsedDocument { text, fields, ..Default::default() })
+}
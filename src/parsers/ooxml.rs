@@ -0,0 +1,74 @@
+use std::path::Path;
+
+use crate::error::DocSenseError;
+use super::markup::{xml_fields, xml_text, XmlLayout};
+use super::package::{open_package, read_part, Package};
+use super::ParsedDocument;
+
+/* Paragraphs and line breaks of Word documents. Deleted revisions and the codes of fields
+   (e.g. PAGE or TOC) are not part of what the document reads. */
+const WORD_LAYOUT: XmlLayout = XmlLayout { skipped: &["delText", "instrText"], lines: &["p", "br", "cr"], spaces: &["tab"] };
+
+/* Paragraphs of the text frames of PowerPoint slides */
+const SLIDE_LAYOUT: XmlLayout = XmlLayout { skipped: &[], lines: &["p", "br"], spaces: &["tab"] };
+
+/* Every string of an Excel workbook is one <si> of the shared strings, without its phonetic readings */
+const SHARED_STRINGS_LAYOUT: XmlLayout = XmlLayout { skipped: &["rPh"], lines: &["si"], spaces: &[] };
+
+/* Core properties of the package kept as fields, by their element name */
+const CORE_PROPERTIES: [(&str, &str); 5] = [
+    ("title", "title"),
+    ("creator", "author"),
+    ("subject", "subject"),
+    ("description", "description"),
+    ("keywords", "keywords"),
+];
+
+/* Parse a Word (.docx), PowerPoint (.pptx) or Excel (.xlsx) document, `format` being its extension.
+   The title of the core properties is also put in front of the text, same as for HTML pages. */
+pub fn parse_ooxml_file(file_path: &Path, source: &[u8], format: &str) -> Result<ParsedDocument, DocSenseError> {
+    let mut package = open_package(file_path, format, source)?;
+    if !package.file_names().any(|name| name == "[Content_Types].xml") {
+        return Err(DocSenseError::parse(file_path, format, "it is a zip archive but no Office Open XML package"));
+    }
+
+    let mut text = String::new();
+    for (part, layout) in text_parts(&package, format) {
+        let Some(source) = read_part(&mut package, &part, file_path, format)? else { continue };
+        let part_text = xml_text(source.as_slice(), layout).map_err(|err| DocSenseError::parse(file_path, format, format!("{part}: {err}")))?;
+        if !part_text.is_empty() {
+            text.push_str(&part_text);
+            text.push('\n');
+        }
+    }
+
+    let fields = match read_part(&mut package, "docProps/core.xml", file_path, format)? {
+        Some(source) => xml_fields(source.as_slice(), &CORE_PROPERTIES).map_err(|err| DocSenseError::parse(file_path, format, format!("docProps/core.xml: {err}")))?,
+        None => Default::default(),
+    };
+
+    if let Some(title) = fields.get("title") {
+        text.insert_str(0, &format!("{title}\n"));
+    }
+    Ok(ParsedDocument { text: text.trim_end().to_owned(), fields, ..Default::default() })
+}
+
+/* Parts of the package holding the text, in reading order, with how the text is laid out in them */
+fn text_parts(package: &Package<'_>, format: &str) -> Vec<(String, &'static XmlLayout<'static>)> {
+    match format {
+        "docx" => vec![("word/document.xml".to_owned(), &WORD_LAYOUT)],
+        "pptx" => {
+            // Slides are numbered in the order they were added, which almost always is the order they are shown in
+            let mut slides = package.file_names()
+                .filter_map(|name| {
+                    let number = name.strip_prefix("ppt/slides/slide")?.strip_suffix(".xml")?.parse::<u32>().ok()?;
+                    Some((number, name.to_owned()))
+                })
+                .collect::<Vec<_>>();
+            slides.sort_unstable();
+            slides.into_iter().map(|(_, name)| (name, &SLIDE_LAYOUT)).collect()
+        }
+        "xlsx" => vec![("xl/sharedStrings.xml".to_owned(), &SHARED_STRINGS_LAYOUT)],
+        _ => Vec::new(),
+    }
+}
@@ -0,0 +1,30 @@
+use std::{io::{Cursor, Read}, path::Path};
+
+use zip::{result::ZipError, ZipArchive};
+
+use crate::error::DocSenseError;
+
+/* Formats like EPUB or Office documents are zip archives of XML parts, called a package */
+pub type Package<'a> = ZipArchive<Cursor<&'a [u8]>>;
+
+pub fn open_package<'a>(file_path: &Path, format: &str, source: &'a [u8]) -> Result<Package<'a>, DocSenseError> {
+    ZipArchive::new(Cursor::new(source)).map_err(|err| DocSenseError::parse(file_path, format, err))
+}
+
+/* Contents of a part of the package, None when it has no such part */
+pub fn read_part(package: &mut Package, name: &str, file_path: &Path, format: &str) -> Result<Option<Vec<u8>>, DocSenseError> {
+    let mut part = match package.by_name(name) {
+        Ok(part) => part,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(err) => return Err(DocSenseError::parse(file_path, format, format!("{name}: {err}"))),
+    };
+    let mut bytes = Vec::new();
+    part.read_to_end(&mut bytes).map_err(|err| DocSenseError::parse(file_path, format, format!("{name}: {err}")))?;
+    Ok(Some(bytes))
+}
+
+/* Same as `read_part`, for a part every package of the format has */
+pub fn read_required_part(package: &mut Package, name: &str, file_path: &Path, format: &str) -> Result<Vec<u8>, DocSenseError> {
+    read_part(package, name, file_path, format)?
+        .ok_or_else(|| DocSenseError::parse(file_path, format, format!("{name} is missing")))
+}
@@ -0,0 +1,32 @@
+use std::path::Path;
+
+use poppler::Document;
+
+use crate::error::DocSenseError;
+use super::{Fields, ParsedDocument};
+
+/* Parse the text of every page of a PDF file with Poppler. Each page is a document of its own,
+   located by `page=N` so links to it open at that page, and has the title, author, subject and
+   keywords of the PDF as fields. */
+pub fn parse_pdf_files(file_path: &Path, source: &[u8]) -> Result<Vec<ParsedDocument>, DocSenseError> {
+    let pdf_file = Document::from_data(source, None).map_err(|err| DocSenseError::parse(file_path, "pdf", err))?;
+
+    let mut fields = Fields::new();
+    let metadata = [("title", pdf_file.title()), ("author", pdf_file.author()), ("subject", pdf_file.subject()), ("keywords", pdf_file.keywords())];
+    for (field, value) in metadata {
+        if let Some(value) = value.filter(|value| !value.trim().is_empty()) {
+            fields.insert(field.to_owned(), value.trim().to_owned());
+        }
+    }
+
+    let mut pages = Vec::new();
+    for i in 0..pdf_file.n_pages() { 
+        // Pages without a text layer (e.g. scans) have nothing to index
+        let Some(text) = pdf_file.page(i).and_then(|page| page.text()) else { continue };
+        if text.trim().is_empty() {
+            continue;
+        }
+        pages.push(ParsedDocument { text: text.as_str().to_owned(), fields: fields.clone(), locator: Some(format!("page={}", i + 1)), ..Default::default() });
+    }
+    Ok(pages)
+}
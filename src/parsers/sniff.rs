@@ -0,0 +1,134 @@
+use std::{io::{Cursor, Read}, path::Path};
+
+use zip::ZipArchive;
+
+use super::compression::open_decompressed;
+use super::ParseOptions;
+use super::encoding::{decode_text, utf16_without_bom};
+
+/* How much of a file is looked at to tell whether it can be indexed at all */
+const SNIFF_LEN: u64 = 8192;
+
+/* Format of a file told by its contents rather than its name, as the extension of that format.
+   Binary formats are recognised by their signature, zip containers (books, office documents) by
+   what they hold, text by decoding as UTF-8 or UTF-16 and then by an XML prolog or an HTML
+   doctype. None for contents DocSense can not read. `source` may also be just the start of a file,
+   a zip archive is then only recognised as one without telling what it holds. */
+pub fn sniff_format(source: &[u8]) -> Option<&'static str> {
+    if source.starts_with(b"%PDF-") {
+        return Some("pdf");
+    }
+    if source.starts_with(b"PK\x03\x04") || source.starts_with(b"PK\x05\x06") {
+        return Some(zip_container(source));
+    }
+    if source.get(257..262) == Some(b"ustar") {
+        return Some("tar");
+    }
+    if !is_text(source) {
+        return None;
+    }
+
+    let start = text_start(source);
+    if starts_with_ignore_case(&start, "<?xml") {
+        // XHTML announces itself with a prolog too
+        let html = start.to_ascii_lowercase().contains("<html");
+        return Some(if html { "xhtml" } else { "xml" });
+    }
+    if starts_with_ignore_case(&start, "<!doctype html") || starts_with_ignore_case(&start, "<html") {
+        return Some("html");
+    }
+    Some("txt")
+}
+
+/* Format of the file at `file_path` by the start of its contents, decompressed if it is compressed.
+   Cheap enough to decide whether a file without an extension is indexed at all. */
+pub fn sniff_file(file_path: &Path) -> Option<&'static str> {
+    let mut start = Vec::new();
+    open_decompressed(file_path).ok()?.take(SNIFF_LEN).read_to_end(&mut start).ok()?;
+    sniff_format(&start)
+}
+
+/* The format a file is parsed as. Its extension decides, unless it has none or its contents are
+   plainly something else: a signature of another binary format, or text where a binary format
+   was expected. Text of one kind named as another (e.g. Markdown or source code) keeps its
+   extension, as does every format a converter is set up for. */
+pub fn resolve_format<'a>(declared: Option<&'a str>, source: &[u8], options: &ParseOptions) -> Option<&'a str> {
+    if declared.is_some_and(|ext| options.converter_for(ext).is_some()) {
+        return declared;
+    }
+    let sniffed = sniff_format(source);
+    match (declared, sniffed) {
+        (Some(declared), Some(sniffed)) if disagrees(declared, sniffed) => Some(sniffed),
+        (Some(declared), _) => Some(declared),
+        (None, sniffed) => sniffed,
+    }
+}
+
+/* Binary formats by their signature, None for the formats which are text */
+fn signature(ext: &str) -> Option<&'static str> {
+    match ext {
+        "pdf" => Some("pdf"),
+        "zip" | "epub" | "docx" | "pptx" | "xlsx" | "odt" | "odp" | "ods" => Some("zip"),
+        "tar" => Some("tar"),
+        _ => None,
+    }
+}
+
+fn disagrees(declared: &str, sniffed: &str) -> bool {
+    match (signature(declared), signature(sniffed)) {
+        // A damaged document may not show what it is, its own parser tells what is wrong with it
+        (Some("zip"), Some("zip")) => sniffed != "zip" && sniffed != declared,
+        (declared, sniffed) => declared != sniffed,
+    }
+}
+
+/* What kind of zip container it is: a book or document names its type in a `mimetype` member,
+   OOXML documents keep their parts in a folder of their own. A plain archive otherwise. */
+fn zip_container(source: &[u8]) -> &'static str {
+    let Ok(mut archive) = ZipArchive::new(Cursor::new(source)) else { return "zip" };
+
+    let mut mimetype = String::new();
+    if let Ok(entry) = archive.by_name("mimetype") {
+        let _ = entry.take(128).read_to_string(&mut mimetype);
+    }
+    match mimetype.trim() {
+        "application/epub+zip" => return "epub",
+        "application/vnd.oasis.opendocument.text" => return "odt",
+        "application/vnd.oasis.opendocument.presentation" => return "odp",
+        "application/vnd.oasis.opendocument.spreadsheet" => return "ods",
+        _ => {}
+    }
+
+    if archive.index_for_name("[Content_Types].xml").is_some() {
+        for (folder, format) in [("word/", "docx"), ("ppt/", "pptx"), ("xl/", "xlsx")] {
+            if archive.file_names().any(|name| name.starts_with(folder)) {
+                return format;
+            }
+        }
+    }
+    "zip"
+}
+
+/* Whether the bytes read as text: UTF-8 (cut off in the middle of a char at the end at most) or
+   UTF-16, with or without a byte order mark, or any other 8 bit encoding without control chars */
+fn is_text(source: &[u8]) -> bool {
+    if source.starts_with(&[0xFF, 0xFE]) || source.starts_with(&[0xFE, 0xFF]) || utf16_without_bom(source).is_some() {
+        return true;
+    }
+    let valid_utf8 = match std::str::from_utf8(source) {
+        Ok(_) => true,
+        Err(err) => err.error_len().is_none(),
+    };
+    // Binary formats are full of zero bytes and other control chars, text has next to none
+    let controls = source.iter().filter(|&&byte| byte < 0x20 && !matches!(byte, b'\t' | b'\n' | b'\r' | 0x0C | 0x1B)).count();
+    !source.contains(&0) && (valid_utf8 || controls * 100 <= source.len())
+}
+
+/* The first few hundred chars of the text, without a byte order mark or leading whitespace */
+fn text_start(source: &[u8]) -> String {
+    decode_text(&source[..source.len().min(1024)]).text.trim_start().to_owned()
+}
+
+fn starts_with_ignore_case(text: &str, prefix: &str) -> bool {
+    text.get(..prefix.len()).is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+}
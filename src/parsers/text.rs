@@ -0,0 +1,8 @@
+use super::encoding::decode_text;
+use super::ParsedDocument;
+
+/* Parse all the text from the TXT File, whatever encoding it is in */
+pub fn parse_txt_file(source: &[u8]) -> ParsedDocument {
+    let decoded = decode_text(source);
+    ParsedDocument { lossy: decoded.lossy, ..ParsedDocument::from(decoded.text) }
+}
@@ -0,0 +1,415 @@
+use super::error::DocSenseError;
+use super::lexer::*;
+
+/* How deep groups and prefixes (+, -, NOT, field:) may nest in a query. The parser recurses for
+   every level, so a query of nothing but parentheses must not run it out of stack. */
+const MAX_DEPTH: usize = 64;
+
+/* A quoted run of terms which has to appear in a document in order, e.g. "neural network".
+   With a slop ("neural network"~5) the terms only have to occur within a window of
+   phrase length + slop positions of each other, in any order. */
+#[derive(Debug)]
+pub struct Phrase {
+    pub terms: Vec<(usize, String)>,    // Term with its offset from the start of the phrase
+    pub slop: Option<usize>,
+}
+
+/* How a clause of a boolean group takes part in matching */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Occur {
+    Must,       // Documents have to match the clause (+term, AND, quoted phrases)
+    Should,     // Optional, only adds to the score unless the group has no required clauses
+    MustNot,    // Documents matching the clause are rejected (-term, NOT)
+}
+
+/* Query AST produced by `parse_query` and walked by the scorer */
+#[derive(Debug)]
+pub enum Node {
+    Term(String),                       // Single lexed token, expanded fuzzily unless it is excluded
+    Phrase(Phrase),
+    Bool(Vec<(Occur, Node)>),
+}
+
+impl Node {
+    /* Restrict the node to a field of the documents, see `field_term` */
+    fn within_field(self, field: &str) -> Node {
+        match self {
+            Node::Term(term) => Node::Term(field_term(field, &term)),
+            Node::Phrase(Phrase { terms, slop }) => Node::Phrase(Phrase {
+                terms: terms.into_iter().map(|(offset, term)| (offset, field_term(field, &term))).collect(),
+                slop,
+            }),
+            Node::Bool(clauses) => Node::Bool(clauses.into_iter().map(|(occur, node)| (occur, node.within_field(field))).collect()),
+        }
+    }
+
+    /* Every free term of the query, so a backend can expand them all in one go */
+    pub fn terms(&self) -> Vec<&str> {
+        match self {
+            Node::Term(term) => vec![term.as_str()],
+            Node::Phrase(_) => Vec::new(),
+            Node::Bool(clauses) => clauses.iter().flat_map(|(_, node)| node.terms()).collect(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Lexeme {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Plus,
+    Minus,
+    Field(String),                      // `name:` in front of a word, phrase or group
+    Phrase(Phrase, Option<Phrase>),    // Also the phrase as written, see `written_tokens`
+    Word(Vec<(usize, String)>, Option<Vec<(usize, String)>>),  // Tokens of a bare word, empty when it only held stop words
+}
+
+/* Tokens of a query word or phrase. Punctuation is dropped but keeps its position,
+   so phrase offsets still line up with the positions stored for the document. */
+fn query_tokens(text: &[char], stem: bool) -> Vec<(usize, String)> {
+    Lexer::new(text).stemmed(stem).positioned()
+        .filter(|(_, token)| token.chars().any(char::is_alphanumeric))
+        .collect()
+}
+
+/* Tokens of a query word or phrase as written, when the index holds unstemmed words too
+   (source code, see `Analysis`) and they differ from the stemmed tokens */
+fn written_tokens(text: &[char], tokens: &[(usize, String)], unstemmed: bool) -> Option<Vec<(usize, String)>> {
+    let written = query_tokens(text, false);
+    (unstemmed && written != tokens).then_some(written)
+}
+
+fn make_phrase(tokens: Vec<(usize, String)>, slop: Option<usize>) -> Phrase {
+    let start = tokens.first().map(|(position, _)| *position).unwrap_or(0);
+    let terms = tokens.into_iter().map(|(position, term)| (position - start, term)).collect();
+    Phrase { terms, slop }
+}
+
+/* Length of the field name if the query continues with `name:` and something right after it */
+fn field_prefix(rest: &[char]) -> Option<usize> {
+    let len = rest.iter().take_while(|c| c.is_ascii_alphanumeric() || **c == '_').count();
+    let glued = rest.get(len + 1).is_some_and(|next| !next.is_whitespace() && *next != ':');
+    // A field name starts with a letter, so e.g. the time 10:30 is searched as it is
+    let named = rest.first().is_some_and(char::is_ascii_alphabetic);
+    (named && rest.get(len) == Some(&':') && glued).then_some(len)
+}
+
+fn scan(query: &[char], unstemmed: bool) -> Vec<Lexeme> {
+    let mut lexemes = Vec::new();
+    let mut rest = query;
+
+    while let Some(&c) = rest.first() {
+        match c {
+            _ if c.is_whitespace() => rest = &rest[1..],
+            '(' => { lexemes.push(Lexeme::Open); rest = &rest[1..]; }
+            ')' => { lexemes.push(Lexeme::Close); rest = &rest[1..]; }
+
+            // A prefix only counts when it is glued to what follows, e.g. +neural or -(a b)
+            '+' | '-' if rest.get(1).is_some_and(|next| !next.is_whitespace()) => {
+                lexemes.push(if c == '+' { Lexeme::Plus } else { Lexeme::Minus });
+                rest = &rest[1..];
+            }
+
+            '"' => {
+                // A missing closing quote makes the phrase run to the end of the query
+                let body = &rest[1..];
+                let end = body.iter().position(|&c| c == '"').unwrap_or(body.len());
+                let text = &body[..end];
+                rest = if end < body.len() { &body[end + 1..] } else { &body[end..] };
+
+                let mut slop = None;
+                if rest.first() == Some(&'~') {
+                    let digits = rest[1..].iter().take_while(|c| c.is_ascii_digit()).count();
+                    slop = rest[1..1 + digits].iter().collect::<String>().parse::<usize>().ok();
+                    rest = &rest[1 + digits..];
+                }
+
+                let tokens = query_tokens(text, true);
+                if tokens.is_empty() {
+                    lexemes.push(Lexeme::Word(tokens, None));
+                } else {
+                    let written = written_tokens(text, &tokens, unstemmed).map(|written| make_phrase(written, slop));
+                    lexemes.push(Lexeme::Phrase(make_phrase(tokens, slop), written));
+                }
+            }
+
+            _ => {
+                // title:word, title:"a phrase" or title:(a OR b). The name has to be glued to what follows.
+                if let Some(len) = field_prefix(rest) {
+                    lexemes.push(Lexeme::Field(rest[..len].iter().collect::<String>().to_lowercase()));
+                    rest = &rest[len + 1..];
+                    continue;
+                }
+
+                let len = rest.iter().position(|&c| c.is_whitespace() || matches!(c, '(' | ')' | '"')).unwrap_or(rest.len());
+                let word = &rest[..len];
+                rest = &rest[len..];
+
+                // Operators are only recognised in upper case, lower case "and"/"or"/"not" are plain words
+                match word.iter().collect::<String>().as_str() {
+                    "AND" | "&&" => lexemes.push(Lexeme::And),
+                    "OR" | "||" => lexemes.push(Lexeme::Or),
+                    "NOT" => lexemes.push(Lexeme::Not),
+                    _ => {
+                        let tokens = query_tokens(word, true);
+                        let written = written_tokens(word, &tokens, unstemmed);
+                        lexemes.push(Lexeme::Word(tokens, written));
+                    }
+                }
+            }
+        }
+    }
+    lexemes
+}
+
+/* Recursive descent parser over the scanned lexemes. From loosest to tightest binding:
+       or   := and (OR and)*
+       and  := seq (AND seq)*
+       seq  := unary+
+       unary:= (+ | - | NOT) unary | field: unary | primary
+       primary := ( or ) | "phrase" | word
+   Every rule yields a clause, an AST node along with how it occurs in its parent. */
+struct Parser {
+    lexemes: Vec<Lexeme>,
+    at: usize,
+    depth: usize,       // Number of groups and prefixes the lexeme at `at` is nested in
+    too_deep: bool,     // Set when they nested deeper than MAX_DEPTH and parsing gave up
+}
+
+type Clause = (Occur, Node);
+
+impl Parser {
+    fn peek(&self) -> Option<&Lexeme> {
+        self.lexemes.get(self.at)
+    }
+
+    fn eat(&mut self, pred: fn(&Lexeme) -> bool) -> bool {
+        if self.peek().is_some_and(pred) {
+            self.at += 1;
+            return true;
+        }
+        false
+    }
+
+    /* Combine several clauses into one group which itself occurs as `Should` */
+    fn group(mut clauses: Vec<Clause>) -> Option<Clause> {
+        match clauses.len() {
+            0 => None,
+            1 => clauses.pop(),
+            _ => Some((Occur::Should, Node::Bool(clauses))),
+        }
+    }
+
+    /* Parse a clause nested one level deeper with `parse`. Past MAX_DEPTH the rest of the query
+       is skipped instead. */
+    fn nested(&mut self, parse: fn(&mut Self) -> Option<Clause>) -> Option<Clause> {
+        if self.depth == MAX_DEPTH {
+            self.too_deep = true;
+            self.at = self.lexemes.len();
+            return None;
+        }
+        self.depth += 1;
+        let clause = parse(self);
+        self.depth -= 1;
+        clause
+    }
+
+    fn parse_or(&mut self) -> Option<Clause> {
+        let mut operands = Vec::new();
+        loop {
+            if let Some(clause) = self.parse_and() {
+                operands.push(clause);
+            }
+            if !self.eat(|l| matches!(l, Lexeme::Or)) {
+                break;
+            }
+        }
+        if operands.len() == 1 {
+            return operands.pop();
+        }
+        // Alternatives are optional, a negated one stays negated inside its own group
+        let operands = operands.into_iter().map(|(occur, node)| match occur {
+            Occur::MustNot => (Occur::Should, Node::Bool(vec![(Occur::MustNot, node)])),
+            _ => (Occur::Should, node),
+        }).collect();
+        Self::group(operands)
+    }
+
+    fn parse_and(&mut self) -> Option<Clause> {
+        let mut operands = Vec::new();
+        loop {
+            if let Some(clause) = self.parse_seq() {
+                operands.push(clause);
+            }
+            if !self.eat(|l| matches!(l, Lexeme::And)) {
+                break;
+            }
+        }
+        if operands.len() == 1 {
+            return operands.pop();
+        }
+        // Both sides of an AND are required, excluded ones stay excluded
+        let operands = operands.into_iter().map(|(occur, node)| match occur {
+            Occur::MustNot => (Occur::MustNot, node),
+            _ => (Occur::Must, node),
+        }).collect();
+        Self::group(operands)
+    }
+
+    fn parse_seq(&mut self) -> Option<Clause> {
+        let mut clauses = Vec::new();
+        while self.peek().is_some_and(|l| !matches!(l, Lexeme::Close | Lexeme::And | Lexeme::Or)) {
+            if let Some(clause) = self.parse_unary() {
+                clauses.push(clause);
+            }
+        }
+        Self::group(clauses)
+    }
+
+    fn parse_unary(&mut self) -> Option<Clause> {
+        if self.eat(|l| matches!(l, Lexeme::Plus)) {
+            return self.nested(Self::parse_unary).map(|(_, node)| (Occur::Must, node));
+        }
+        if self.eat(|l| matches!(l, Lexeme::Minus | Lexeme::Not)) {
+            return self.nested(Self::parse_unary).map(|(_, node)| (Occur::MustNot, node));
+        }
+        if let Some(Lexeme::Field(field)) = self.peek() {
+            let field = field.clone();
+            self.at += 1;
+            return self.nested(Self::parse_unary).map(|(occur, node)| (occur, node.within_field(&field)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Option<Clause> {
+        if self.peek().is_none_or(|l| matches!(l, Lexeme::Close | Lexeme::And | Lexeme::Or)) {
+            return None;
+        }
+        self.at += 1;
+        match std::mem::replace(&mut self.lexemes[self.at - 1], Lexeme::Word(Vec::new(), None)) {
+            Lexeme::Open => {
+                let clause = self.nested(Self::parse_or);
+                self.eat(|l| matches!(l, Lexeme::Close));
+                clause
+            }
+            // Quoted phrases are required unless they are negated or one side of an OR
+            Lexeme::Phrase(phrase, written) => Some((Occur::Must, either(Node::Phrase(phrase), written.map(Node::Phrase)))),
+            Lexeme::Word(tokens, written) => word_node(tokens).map(|node| (Occur::Should, either(node, written.and_then(word_node)))),
+            // Operators without an operand in front of them are ignored
+            _ => None,
+        }
+    }
+}
+
+/* A word split by the lexer (e.g. "e-mail") has to match as a phrase */
+fn word_node(mut tokens: Vec<(usize, String)>) -> Option<Node> {
+    match tokens.len() {
+        0 => None,
+        1 => tokens.pop().map(|(_, term)| Node::Term(term)),
+        _ => Some(Node::Phrase(make_phrase(tokens, None))),
+    }
+}
+
+/* A node matching its stemmed form or the form as written, if there is one */
+fn either(stemmed: Node, written: Option<Node>) -> Node {
+    match written {
+        Some(written) => Node::Bool(vec![(Occur::Should, stemmed), (Occur::Should, written)]),
+        None => stemmed,
+    }
+}
+
+/* Parse the raw query into an AST. Supports +must, -exclude, AND, OR, NOT, parentheses,
+   quoted phrases with an optional ~N proximity and field:term restrictions. Malformed input
+   does not fail, unbalanced parentheses and dangling operators are ignored, only nesting deeper
+   than MAX_DEPTH does. Returns None when nothing is searchable. With `unstemmed` words also match
+   as they are written, for source code which is indexed without stemming. */
+pub fn parse_query(query: &[char], unstemmed: bool) -> Result<Option<Node>, DocSenseError> {
+    let mut parser = Parser { lexemes: scan(query, unstemmed), at: 0, depth: 0, too_deep: false };
+    let mut clauses = Vec::new();
+    while parser.at < parser.lexemes.len() {
+        match parser.parse_or() {
+            Some(clause) => clauses.push(clause),
+            // Skip a stray closing parenthesis and keep going
+            None => parser.at += 1,
+        }
+    }
+
+    if parser.too_deep {
+        return Err(DocSenseError::Query { query: query.iter().collect(), reason: format!("nests groups or operators deeper than {MAX_DEPTH} levels") });
+    }
+
+    Ok(match Parser::group(clauses) {
+        Some((Occur::MustNot, node)) => Some(Node::Bool(vec![(Occur::MustNot, node)])),
+        Some((_, node)) => Some(node),
+        None => None,
+    })
+}
+
+/* Number of places in a document where the phrase matches, given the sorted positions of
+   each phrase term in that document (same order as `phrase.terms`). */
+pub fn phrase_matches(phrase: &Phrase, positions: &[&[u32]]) -> usize {
+    if positions.is_empty() || positions.iter().any(|p| p.is_empty()) {
+        return 0;
+    }
+
+    match phrase.slop {
+        None => {
+            // Every term has to sit exactly at its offset from where the phrase starts
+            let (first_offset, _) = phrase.terms[0];
+            positions[0].iter().filter(|&&p| {
+                let Some(start) = (p as usize).checked_sub(first_offset) else { return false };
+                phrase.terms.iter().zip(positions).all(|((offset, _), term_positions)| {
+                    term_positions.binary_search(&((start + offset) as u32)).is_ok()
+                })
+            }).count()
+        }
+
+        Some(slop) => {
+            // Sweep a window over the merged positions, always advancing the term at the
+            // smallest position, and count the windows narrow enough to hold the phrase.
+            let span = phrase.terms.iter().map(|(offset, _)| *offset).max().unwrap_or(0) + slop;
+            let mut cursors = vec![0usize; positions.len()];
+            let mut matches = 0;
+            loop {
+                let heads = cursors.iter().zip(positions).map(|(&i, p)| p[i]);
+                let (lowest, highest) = heads.fold((u32::MAX, 0), |(lo, hi), p| (lo.min(p), hi.max(p)));
+                if (highest - lowest) as usize <= span {
+                    matches += 1;
+                }
+
+                let Some(advance) = (0..positions.len()).find(|&i| positions[i][cursors[i]] == lowest) else { break };
+                cursors[advance] += 1;
+                if cursors[advance] == positions[advance].len() {
+                    break;
+                }
+            }
+            matches
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(query: &str) -> Result<Option<Node>, DocSenseError> {
+        parse_query(&query.chars().collect::<Vec<_>>(), false)
+    }
+
+    #[test]
+    fn field_names_hold_digits() {
+        assert_eq!(parse("col1:abc q2:(x y)").unwrap().unwrap().terms(), ["col1:ABC", "q2:X", "q2:Y"]);
+        assert!(matches!(parse("10:30").unwrap(), Some(Node::Phrase(_))));
+    }
+
+    #[test]
+    fn deep_nesting_is_an_error() {
+        assert!(parse(&format!("{}neural{}", "(".repeat(MAX_DEPTH), ")".repeat(MAX_DEPTH))).unwrap().is_some());
+        for query in ["(".repeat(300_000), "+-".repeat(150_000), "title:".repeat(100_000)] {
+            assert!(matches!(parse(&format!("{query}neural")), Err(DocSenseError::Query { .. })));
+        }
+    }
+}
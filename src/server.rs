@@ -4,15 +4,11 @@ use std::{
     fs::{self, File},
     io::{self, ErrorKind},
     path::{Path, PathBuf},
-    process::exit,
     str,
-    sync::{Arc, Mutex}
 }; 
 
 use colored::Colorize;
-use crate::RankMethod;
-
-use super::model::*;
+use docsense::{parsers::{inner_extension, read_decompressed, read_member, sniff_file, sniff_format, split_member_path}, DocSenseError, Index, RankMethod, SearchOptions};
 
 pub fn serve_status_code(request: Request, statuscode: i32) -> io::Result<()> {
     return request.respond(Response::from_string(statuscode.to_string()).with_status_code(StatusCode(statuscode as u16)));
@@ -57,7 +53,7 @@ pub fn serve_embedded(request: Request, content: &'static str, content_type: &'s
 }
 
 
-pub fn serve_api_search(mut request: Request, model: Arc<Mutex<InMemoryModel>>, rank_method: RankMethod) -> io::Result<()>{
+pub fn serve_api_search(mut request: Request, index: &Index, rank_method: RankMethod) -> io::Result<()>{
     let mut buf = Vec::new();
     // Read the entire body of request 
     if let Err(err) = request.as_reader().read_to_end(&mut buf) {
@@ -66,30 +62,30 @@ pub fn serve_api_search(mut request: Request, model: Arc<Mutex<InMemoryModel>>,
     }
 
     let body = match str::from_utf8(&mut buf) {
-        Ok(body) => body.chars().collect::<Vec<_>>(), 
+        Ok(body) => body, 
         Err(err) => {
             eprintln!("{}: Could not interpret body as UTF-8 string as {err}", "ERROR".bold().red(), err = err.to_string().red());
             return serve_400(request, "Body must be a valid UTF-8 string");
         }
     };
 
-    println!("Recieved Query: \'{}\'", body.iter().collect::<String>().bright_blue());
+    println!("Recieved Query: \'{}\'", body.bright_blue());
 
-    let model = model.lock().unwrap();
-    let results = match model.search_query(&body, &model, rank_method) {
+    // Collect top-10 results along with their snippets. Only documents matching the query are
+    // returned, so a zero rank (e.g. a required term present in every document) is still a hit.
+    let results = match index.search(body, &SearchOptions { rank_method, limit: 10 }) {
         Ok(results) => results, 
-        Err(()) => return serve_500(request)
+        Err(err @ DocSenseError::Query { .. }) => return serve_400(request, &err.to_string()),
+        Err(err) => {
+            eprintln!("{}: {err}", "ERROR".bold().red());
+            return serve_500(request);
+        }
     };
-    
-    // Collect top-10 results with rank > 0. Use filter (not break) in case
-    // fuzzy results produce a non-zero score after a zero-score result.
-    let content: Vec<_> = results.iter()
-        .filter(|(_, rank)| *rank > 0.0)
-        .take(10)
-        .inspect(|(path, rank)| println!("      {} => {}", path.display(), rank))
-        .collect();
-
-    let json = match serde_json::to_string(&content) {
+    for result in &results {
+        println!("      {} => {}", result.path.display(), result.rank);
+    }
+
+    let json = match serde_json::to_string(&results) {
         Ok(json) => json, 
         Err(err) => {
             eprintln!("{}: could not convert search results to JSON as {err}", "ERROR".bold().red(), err = err.to_string().red());
@@ -105,18 +101,13 @@ pub fn serve_api_search(mut request: Request, model: Arc<Mutex<InMemoryModel>>,
     return request.respond(response);
 }
 
-pub fn serve_api_stats(request: Request, model: Arc<Mutex<InMemoryModel>>) -> io::Result<()> {
-    use serde::Serialize;
-    #[derive(Default, Serialize)]
-    struct Stats {
-        doc_count: usize, 
-        unique_term_count: usize, 
-    }
-
-    let model = model.lock().unwrap();
-    let stats = Stats {
-        doc_count: model.docs.len(), 
-        unique_term_count: model.gtf.len()
+pub fn serve_api_stats(request: Request, index: &Index) -> io::Result<()> {
+    let stats = match index.stats() {
+        Ok(stats) => stats,
+        Err(err) => {
+            eprintln!("{}: {err}", "ERROR".bold().red());
+            return serve_500(request);
+        }
     };
 
     let json = match serde_json::to_string(&stats) {
@@ -188,14 +179,26 @@ fn extract_query_param(url: &str, name: &str) -> Option<String> {
     None
 }
 
-fn content_type_for_file_path(path: &Path) -> &str {
-    let extension = path.extension().and_then(|ext| ext.to_str()).map(|ext| ext.to_lowercase());
+/* Content type of a file by its extension, or by its contents when it has none. Compressed files
+   are typed by what they hold, e.g. spec.txt.gz as text. */
+fn content_type_for_file_path(path: &Path) -> &'static str {
+    content_type_for_format(inner_extension(path).or_else(|| sniff_file(path)))
+}
+
+fn content_type_for_format(format: Option<&str>) -> &'static str {
+    let extension = format.map(|ext| ext.to_lowercase());
     match extension.as_deref() {
         Some("html") => "text/html; charset=utf-8",
         Some("xhtml") => "application/xhtml+xml; charset=utf-8",
         Some("xml") => "application/xml; charset=utf-8",
         Some("txt") => "text/plain; charset=utf-8",
         Some("md") => "text/markdown; charset=utf-8",
+        Some("tex") => "text/x-tex; charset=utf-8",
+        Some("bib") => "text/x-bibtex; charset=utf-8",
+        Some("csv") => "text/csv; charset=utf-8",
+        Some("tsv") => "text/tab-separated-values; charset=utf-8",
+        Some("json") => "application/json; charset=utf-8",
+        Some("jsonl") | Some("ndjson") => "application/x-ndjson; charset=utf-8",
         Some("pdf") => "application/pdf",
         _ => "application/octet-stream",
     }
@@ -207,7 +210,11 @@ fn serve_file_request(request: Request, url: &str, root_dir: &Path) -> io::Resul
         None => return serve_400(request, "Missing file path query parameter"),
     };
 
-    let requested_path = Path::new(&path_query);
+    // A member of an archive is served out of it, e.g. bundle.zip!/docs/intro.md
+    let (requested_path, member) = match split_member_path(Path::new(&path_query)) {
+        Some((archive_path, member)) => (archive_path, Some(member)),
+        None => (PathBuf::from(&path_query), None),
+    };
     let normalized_path = if requested_path.is_absolute() {
         requested_path.to_path_buf()
     } else {
@@ -226,11 +233,43 @@ fn serve_file_request(request: Request, url: &str, root_dir: &Path) -> io::Resul
         return serve_404(request);
     }
 
+    if let Some(member) = member {
+        return match read_member(&canonicalized, &member) {
+            Ok(contents) => {
+                let content_type = content_type_for_format(inner_extension(Path::new(&member)).or_else(|| sniff_format(&contents)));
+                serve_contents(request, Ok(contents), content_type)
+            }
+            // Most likely there is no such member in the archive
+            Err(err) => {
+                eprintln!("{}: {err}", "ERROR".bold().red(), err = err.to_string().red());
+                serve_404(request)
+            }
+        };
+    }
+
     let content_type = content_type_for_file_path(&canonicalized);
-    serve_static_file(request, canonicalized.to_str().unwrap_or_default(), content_type)
+    if inner_extension(&canonicalized) == canonicalized.extension().and_then(|ext| ext.to_str()) {
+        return serve_static_file(request, canonicalized.to_str().unwrap_or_default(), content_type);
+    }
+
+    // Compressed files are sent decompressed, browsers could not show most of them otherwise
+    serve_contents(request, read_decompressed(&canonicalized), content_type)
+}
+
+fn serve_contents(request: Request, contents: Result<Vec<u8>, DocSenseError>, content_type: &str) -> io::Result<()> {
+    match contents {
+        Ok(contents) => {
+            let header = Header::from_bytes("Content-Type", content_type).expect("Should be a valid Content-Type while passing the header.");
+            request.respond(Response::from_data(contents).with_header(header))
+        }
+        Err(err) => {
+            eprintln!("{}: {err}", "ERROR".bold().red(), err = err.to_string().red());
+            serve_500(request)
+        }
+    }
 }
 
-pub fn serve_request(request: Request, model: Arc<Mutex<InMemoryModel>>, rank_method: RankMethod, root_dir: &Path) -> io::Result<()> {
+pub fn serve_request(request: Request, index: &Index, rank_method: RankMethod, root_dir: &Path) -> io::Result<()> {
     let request_url = request.url().to_string();
     println!("{info}: Received request! method: [{req}], url: {url:?}",
         info = "INFO".bright_cyan(), 
@@ -255,11 +294,11 @@ pub fn serve_request(request: Request, model: Arc<Mutex<InMemoryModel>>, rank_me
         }
 
         (Method::Post, "/api/search") => {
-            serve_api_search(request, model, rank_method)?
+            serve_api_search(request, index, rank_method)?
         }
 
         (Method::Get, "/api/stats") => {
-            serve_api_stats(request, model)?
+            serve_api_stats(request, index)?
         }
 
         _ => {
@@ -271,17 +310,16 @@ pub fn serve_request(request: Request, model: Arc<Mutex<InMemoryModel>>, rank_me
 }
 
 
-pub fn start(address: &str, model: Arc<Mutex<InMemoryModel>>, rank_method: RankMethod, root_dir: PathBuf) -> Result<(), ()> {
+pub fn start(address: &str, index: Index, rank_method: RankMethod, root_dir: PathBuf) -> Result<(), DocSenseError> {
     let address_str = "http://".to_string() + &address + "/"; 
     let server = Server::http(address).map_err(|err| {
-        eprintln!("{}: Could not create initiate server at {address} as {err}", "ERROR".bold().red(), address = address.bold().bright_blue(), err = err.to_string().red());
-        exit(1);
-    }).unwrap();
+        DocSenseError::Bind { address: address.to_owned(), message: err.to_string() }
+    })?;
 
     println!("{info}: Server Listening at: {address}", info = "INFO".bright_cyan(), address = address_str.cyan());
 
     for request in server.incoming_requests() {
-        serve_request(request, Arc::clone(&model), rank_method.clone(), &root_dir).map_err(|err| {
+        serve_request(request, &index, rank_method.clone(), &root_dir).map_err(|err| {
             eprintln!("{}: Failed to serve the request as {err}", "ERROR".bold().red(), err = err.to_string().red());
         }).ok(); // <- Don't stop here continue serving requests
     }
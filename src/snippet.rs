@@ -0,0 +1,144 @@
+use std::{collections::HashSet, ops::Range};
+
+use colored::Colorize;
+use serde::Serialize;
+
+use super::lexer::*;
+
+/* Rough number of chars shown by a snippet around its matches */
+const SNIPPET_LENGTH: usize = 200;
+
+/* An excerpt of a document around some of the terms it matched a query by */
+#[derive(Debug, Serialize)]
+pub struct Snippet {
+    pub text: String,
+    // Start and end offset of every match within `text`, counted in chars (unicode scalar values)
+    pub highlights: Vec<(usize, usize)>,
+}
+
+impl Snippet {
+    /* The snippet text with its matches highlighted for the terminal */
+    pub fn to_colored_string(&self) -> String {
+        let chars = self.text.chars().collect::<Vec<_>>();
+        let mut colored = String::with_capacity(self.text.len());
+        let mut at = 0;
+        for &(start, end) in &self.highlights {
+            colored.extend(&chars[at..start]);
+            colored.push_str(&chars[start..end].iter().collect::<String>().bold().yellow().to_string());
+            at = end;
+        }
+        colored.extend(&chars[at..]);
+        colored
+    }
+}
+
+/* Pick up to `max_snippets` excerpts of the content, preferring the ones holding the most
+   distinct matched terms, then the most matches. They are returned in document order. */
+pub fn make_snippets(content: &[char], lexer: Lexer, terms: &HashSet<String>, max_snippets: usize) -> Vec<Snippet> {
+    // `lexer` lexes the content the way it was indexed. A hit inside a longer one, i.e. a word
+    // of an identifier which matched as a whole, is only highlighted once.
+    let mut hits: Vec<Token> = Vec::new();
+    for token in lexer.tokens().filter(|token| terms.contains(&token.term)) {
+        if hits.last().is_none_or(|last| token.span.start >= last.span.end) {
+            hits.push(token);
+        }
+    }
+    if hits.is_empty() {
+        // Nothing to highlight, show how the document starts instead
+        let end = content.len().min(SNIPPET_LENGTH);
+        return vec![excerpt(content, 0..end, &[])];
+    }
+
+    let mut taken = vec![false; hits.len()];
+    let mut windows = Vec::new();
+    for _ in 0..max_snippets {
+        // (distinct terms, hits, first hit, last hit) of the best window left
+        let mut best: Option<(usize, usize, usize, usize)> = None;
+        for first in (0..hits.len()).filter(|&i| !taken[i]) {
+            let mut seen = HashSet::new();
+            let mut last = first;
+            for (i, hit) in hits.iter().enumerate().skip(first) {
+                if taken[i] || hit.span.end - hits[first].span.start > SNIPPET_LENGTH {
+                    break;
+                }
+                seen.insert(hit.term.as_str());
+                last = i;
+            }
+            let score = (seen.len(), last - first + 1);
+            if best.is_none_or(|(terms, count, _, _)| score > (terms, count)) {
+                best = Some((score.0, score.1, first, last));
+            }
+        }
+
+        let Some((_, _, first, last)) = best else { break };
+        taken[first..=last].iter_mut().for_each(|taken| *taken = true);
+        windows.push(first..last + 1);
+    }
+    windows.sort_by_key(|window| window.start);
+
+    windows.into_iter().map(|window| {
+        let hits = &hits[window];
+        let (first, last) = (hits[0].span.start, hits[hits.len() - 1].span.end);
+        // Center the matches, giving them the same amount of context on either side
+        let padding = SNIPPET_LENGTH.saturating_sub(last - first) / 2;
+        let range = first.saturating_sub(padding)..(last + padding).min(content.len());
+        excerpt(content, range, hits)
+    }).collect()
+}
+
+/* Cut the range out of the content with whitespace collapsed, without splitting words
+   at its edges, and translate the spans of the hits into offsets of the snippet text */
+fn excerpt(content: &[char], range: Range<usize>, hits: &[Token]) -> Snippet {
+    let (mut start, mut end) = (range.start, range.end);
+    let first_hit = hits.first().map_or(end, |hit| hit.span.start);
+    let last_hit = hits.last().map_or(start, |hit| hit.span.end);
+    if start > 0 {
+        if let Some(space) = content[start..first_hit].iter().position(|c| c.is_whitespace()) {
+            start += space + 1;
+        }
+    }
+    if end < content.len() {
+        if let Some(space) = content[last_hit..end].iter().rposition(|c| c.is_whitespace()) {
+            end = last_hit + space;
+        }
+    }
+
+    let mut text = String::new();
+    let mut length = 0;
+    if start > 0 {
+        text.push_str("… ");
+        length += 2;
+    }
+
+    // offsets[i] is where the char at content[start + i] ends up in the text
+    let mut offsets = Vec::with_capacity(end - start + 1);
+    let mut previous_space = true;
+    for &c in &content[start..end] {
+        offsets.push(length);
+        if c.is_whitespace() {
+            if !previous_space {
+                text.push(' ');
+                length += 1;
+            }
+            previous_space = true;
+        } else {
+            text.push(c);
+            length += 1;
+            previous_space = false;
+        }
+    }
+    offsets.push(length);
+
+    if text.ends_with(' ') {
+        text.pop();
+    }
+    if end < content.len() {
+        text.push_str(" …");
+    }
+
+    let highlights = hits.iter()
+        .filter(|hit| hit.span.start >= start && hit.span.end <= end)
+        .map(|hit| (offsets[hit.span.start - start], offsets[hit.span.end - start]))
+        .collect();
+    Snippet { text, highlights }
+}
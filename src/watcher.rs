@@ -0,0 +1,164 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::{mpsc::{self, Receiver, RecvTimeoutError}, Arc},
+    time::{Duration, Instant},
+};
+
+use colored::Colorize;
+use notify::{event::{AccessKind, AccessMode}, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use docsense::{indexer::{append_folder_to_model, index_files, IndexEvent}, parsers::is_indexable, DocSenseError, Index};
+
+use crate::{report_progress, report_skipped, save_index};
+
+/* Changes are only applied once the directory was quiet for this long, so a burst of
+   events (an editor saving, a folder being copied) is handled as a single batch */
+const DEBOUNCE_DELAY: Duration = Duration::from_millis(500);
+
+/* A batch is applied after this long even while events keep coming in */
+const MAX_BATCH_DELAY: Duration = Duration::from_secs(5);
+
+/* Index changes are written to disk at most this often */
+const PERSIST_INTERVAL: Duration = Duration::from_secs(30);
+
+/* Watches a directory tree (inotify on Linux) and keeps a model in sync with it */
+pub struct DirectoryWatcher {
+    root_dir: PathBuf,
+    events: Receiver<notify::Result<Event>>,
+    _watcher: RecommendedWatcher,       // Stops watching once dropped
+}
+
+impl DirectoryWatcher {
+    /* Start watching `root_dir`. Events are queued right away, so changes made while the
+       directory is still being indexed are picked up by `run` afterwards. */
+    pub fn new(root_dir: &Path) -> Result<Self, DocSenseError> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher.watch(root_dir, RecursiveMode::Recursive)?;
+        Ok(Self { root_dir: root_dir.to_path_buf(), events, _watcher: watcher })
+    }
+
+    /* Apply every change of the watched directory to the index until the watcher shuts down,
+       saving the index periodically */
+    pub fn run(self, index: Index, jobs: usize) {
+        println!("{}: Watching {} for changes", "INFO".cyan(), self.root_dir.display().to_string().bright_cyan());
+
+        let mut changed_paths = HashSet::new();
+        let mut batch_started = Instant::now();
+        let mut unsaved = false;
+        let mut last_save = Instant::now();
+
+        loop {
+            let timeout = if !changed_paths.is_empty() {
+                DEBOUNCE_DELAY.min(MAX_BATCH_DELAY.saturating_sub(batch_started.elapsed()))
+            } else if unsaved {
+                PERSIST_INTERVAL.saturating_sub(last_save.elapsed())
+            } else {
+                Duration::MAX
+            };
+
+            match self.events.recv_timeout(timeout) {
+                Ok(Ok(event)) => {
+                    let paths = event.paths.into_iter().filter(|path| self.is_relevant(&event.kind, path)).collect::<Vec<_>>();
+                    if !paths.is_empty() && changed_paths.is_empty() {
+                        batch_started = Instant::now();
+                    }
+                    changed_paths.extend(paths);
+                    // Keep collecting until things calm down, unless the batch is overdue
+                    if batch_started.elapsed() < MAX_BATCH_DELAY {
+                        continue;
+                    }
+                }
+                Ok(Err(err)) => {
+                    eprintln!("{}: File system watcher failed as {err}", "ERROR".bold().red(), err = err.to_string().red());
+                    continue;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            if !changed_paths.is_empty() {
+                match apply_changes(changed_paths.drain().collect(), &index, jobs) {
+                    Ok(changes) => unsaved |= changes > 0,
+                    Err(err) => eprintln!("{}: Failed to apply changes of {dir} to the index as {err}", "ERROR".bold().red(), dir = self.root_dir.display().to_string().bright_blue()),
+                }
+            }
+
+            if unsaved && last_save.elapsed() >= PERSIST_INTERVAL {
+                match save_index(&index) {
+                    Ok(()) => unsaved = false,
+                    Err(err) => eprintln!("{}: {err}", "ERROR".bold().red()),
+                }
+                last_save = Instant::now();
+            }
+        }
+
+        if unsaved {
+            if let Err(err) = save_index(&index) {
+                eprintln!("{}: {err}", "ERROR".bold().red());
+            }
+        }
+        eprintln!("{}: Stopped watching {}", "ERROR".bold().red(), self.root_dir.display().to_string().bright_blue());
+    }
+
+    /* Skip plain reads and anything inside hidden files or folders, the index itself included */
+    fn is_relevant(&self, kind: &EventKind, path: &Path) -> bool {
+        let changes_content = match kind {
+            EventKind::Access(AccessKind::Close(AccessMode::Write)) => true,
+            EventKind::Access(_) | EventKind::Other => false,
+            _ => true,
+        };
+        let hidden = path.strip_prefix(&self.root_dir).map_or(true, |relative| {
+            relative.components().any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+        });
+        changes_content && !hidden
+    }
+}
+
+/* Bring the model up to date with the changed paths. Returns the number of added, updated and removed documents. */
+fn apply_changes(paths: Vec<PathBuf>, index: &Index, jobs: usize) -> Result<usize, DocSenseError> {
+    let (model, options) = (index.model(), index.parse_options());
+    let mut pending = Vec::new();
+    let mut removed = Vec::new();
+
+    for path in paths {
+        // Deleted, or the old name of something that was moved
+        let Ok(metadata) = fs::metadata(&path) else {
+            removed.push(path);
+            continue;
+        };
+
+        if metadata.is_dir() {
+            append_folder_to_model(&path, Arc::clone(&model), options, &mut pending, &mut HashSet::new(), &report_progress)?;
+            continue;
+        }
+
+        let Ok(last_modified) = metadata.modified() else { continue };
+        if is_indexable(&path, options) && model.lock().unwrap().requires_reindexing(&path, last_modified)? {
+            pending.push((path, last_modified));
+        }
+    }
+
+    // A new folder and the files inside of it can show up in the same batch
+    pending.sort();
+    pending.dedup_by(|(a, _), (b, _)| a == b);
+
+    let mut changes = 0;
+    if !removed.is_empty() {
+        let mut model = model.lock().unwrap();
+        for path in model.indexed_paths()? {
+            // A removed folder takes every document inside of it along
+            if removed.iter().any(|removed| path.starts_with(removed)) {
+                model.remove_documents(&path)?;
+                report_progress(IndexEvent::Removed(&path));
+                changes += 1;
+            }
+        }
+    }
+
+    let summary = index_files(&pending, model, options, jobs, &report_progress)?;
+    report_skipped(&summary);
+    Ok(changes + summary.indexed + summary.removed)
+}